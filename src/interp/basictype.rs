@@ -1,30 +1,82 @@
 //! Basic types
 
-extern crate num;
+use std::fmt;
+use std::rc::Rc;
+use super::{SExpr, Data, Literal, Nil, Env, Closure, MutablePair};
+use super::number::NumericType;
+use super::operator;
+use super::operator::OperatorType;
+use super::eval::{Captured, Subcontinuation};
+use super::eval::syntax::Rules;
 
-use self::num::{rational, bigint};
-use super::SExpr;
-// use self::num::complex;
-
-#[deriving(Show, Clone, Eq)]
-pub enum NumericType {
-//    Complex(complex::Complex),
-    BigRational(rational::BigRational),
-//    Rational(rational::Rational),
-//    BigInteger(bigint::BigInt),
-    Floating(f64),
-    Integer(int),
-//    UInteger(uint)
-}
-
-#[deriving(Show, Clone, Eq)]
+///Code is made of SExpr cells, which is what the reader produces, while
+///lists built while running are made of Pairs.
+#[deriving(Clone, Eq)]
 pub enum BasicType {
     Boolean(bool),
     Character(char),
     Number(NumericType),
     String(StrBuf),
-    Symbol(StrBuf),
-    Pair(Box<BasicType>, Box<BasicType>),
-    List,
-    Procedure(Box<SExpr>)
+    Pair(Rc<MutablePair>),
+    Procedure(Rc<Closure>),
+    Primitive(OperatorType), //a built in operator, used as a value
+    Continuation(Rc<Captured>),
+    Composable(Rc<Subcontinuation>),
+    Environment(Env), //what eval evaluates an expression in
+    Syntax(Rc<Rules>),
+    Macro(Box<SExpr>)
+}
+
+///Prints values the way they would be written in source, so that what the
+///REPL shows can be read back in.
+impl fmt::Show for BasicType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Boolean(true)       => write!(f, "{}", "#t"),
+            Boolean(false)      => write!(f, "{}", "#f"),
+            Character(' ')      => write!(f, "{}", "#\\space"),
+            Character('\n')     => write!(f, "{}", "#\\newline"),
+            Character(c)        => write!(f, "{}{}", "#\\", c),
+            Number(ref x)       => write!(f, "{}", x),
+            String(ref x)       => write!(f, "{}{}{}", '"', x, '"'),
+            Pair(ref pair)      => {
+                try!(write!(f, "({}", *pair.car.borrow()));
+                let mut rest = pair.cdr.borrow().clone();
+                loop {
+                    rest = match rest {
+                        Data(Literal(Pair(next)))   => {
+                            try!(write!(f, " {}", *next.car.borrow()));
+                            next.cdr.borrow().clone()
+                        }
+                        Nil     => break,
+                        other   => {
+                            try!(write!(f, " . {}", other));
+                            break
+                        }
+                    }
+                }
+                write!(f, ")")
+            }
+            Procedure(_)        => write!(f, "{}", "#<procedure>"),
+            Primitive(op)       => {
+                let arguments = match operator::arity(op) {
+                    Some((1, Some(1)))  => "1 argument".to_strbuf(),
+                    Some((least, Some(greatest))) if least == greatest  => {
+                        format!("{} arguments", least).to_strbuf()
+                    }
+                    Some((least, Some(greatest)))   => {
+                        format!("{} to {} arguments", least, greatest).to_strbuf()
+                    }
+                    Some((0, None))     => "any number of arguments".to_strbuf(),
+                    Some((least, None)) => format!("at least {} arguments", least).to_strbuf(),
+                    None                => unreachable!()
+                };
+                write!(f, "{}{} ({}){}", "#<procedure ", operator::to_str(op), arguments, ">")
+            }
+            Continuation(_) | Composable(_) => write!(f, "{}", "#<continuation>"),
+            Environment(_)      => write!(f, "{}", "#<environment>"),
+            Syntax(_)           => write!(f, "{}", "#<syntax>"),
+            Macro(_)            => write!(f, "{}", "#<macro>")
+        }
+    }
 }
@@ -1,177 +1,1585 @@
-extern crate collections;
-
-use self::collections::HashMap;
-
-use super::super::{SExpr, Cons, Nil, Data, DataType, car, cdr};
-use super::super::{Operator, Literal, Variable};
-use super::super::{Environment, lookup};
-use super::super::basictype::BasicType;
-use super::super::basictype::{Boolean, Character, Number, String, Symbol, Pair, List, Procedure};
-use super::super::basictype::{NumericType, Floating, Integer};
-use super::super::operator::{OperatorType, Add, Mul, Define, Lambda};
-pub fn eval(terms: &Vec<DataType>, mut environment: &mut Environment) -> Result<BasicType, StrBuf> {
-    if terms.len() == 0 {
-        return Err("No terms!".to_strbuf())
-    } else if terms.len() == 1 {
-        match terms.get(0) {
-            &Operator(x)    => return Ok(Symbol(x.to_str().to_strbuf())),
-            &Literal(ref x)     => return Ok(x.clone()),
-            &Variable(ref x)    => match try!(lookup(x.as_slice(), environment)) {
-                    Data(ty) => match ty {
-                        Literal(value ) => return Ok(value),
-                        _               => return Err("I can't handle this!".to_strbuf())
-                    },
-                    _   => return Err("I give up".to_strbuf())
-            }
+use std::rc;
+use std::rc::Rc;
+use std::cmp::{Less, Greater};
+
+use super::super::{SExpr, Cons, Nil, Data, DataType};
+use super::super::{Operator, Literal, Variable, Alias};
+use super::super::{Env, Environment, Closure, RispError};
+use super::super::{resolve, assign, identifier_key, base_name, cons, list_to_vec, vec_to_list};
+use super::super::{vec_to_improper_list, make_pair, vec_to_data, to_data, to_syntax};
+use super::super::basictype;
+use super::super::basictype::{Boolean, Number, Procedure, Primitive, Syntax, Macro};
+use super::super::list;
+use super::super::number;
+use super::super::number::{NumericType, Integer};
+use super::super::operator;
+use super::super::operator::{OperatorType, Define, Set, Lambda, Quote, Constructor};
+use super::super::operator::{Car, Cdr, Cxr, List, SetCar, SetCdr, Null, IsPair, IsList};
+use super::super::operator::{Length, Append, Reverse, ListTail, ListRef, ListCopy};
+use super::super::operator::{Map, ForEach, IsEq, IsEqv, IsEqual};
+use super::super::operator::{Apply, InteractionEnvironment, SchemeReportEnvironment};
+use super::super::operator::NullEnvironment;
+use super::super::operator::{Let, LetStar, Letrec, LetrecStar, Do, Begin};
+use super::super::operator::{Add, Sub, Mul, Div, Rem, IsEven, Quotient, Modulo};
+use super::super::operator::{FloorDiv, TruncateDiv, Gcd, Lcm, Abs, Min, Max, Exact, Inexact};
+use super::super::operator::{Floor, Ceiling, Round, Truncate};
+use super::super::operator::{Sqrt, ExactIntegerSqrt, Expt, Exp, Log, Sin, Cos, Tan, Asin};
+use super::super::operator::{Acos, Atan, Square, IsNan, IsInfinite, IsFinite};
+use super::super::operator::{IsZero, IsPositive, IsNegative, IsOdd, IsExact, IsInexact};
+use super::super::operator::{IsInteger, IsRational, IsReal, IsComplex, IsNumber};
+use super::super::operator::{BitwiseAnd, BitwiseOr, BitwiseXor, BitwiseNot, ArithmeticShift};
+use super::super::operator::{BitCount, IsBitSet, NumberToString, StringToNumber};
+use super::super::operator::{If, And, Or, CallCC, DynamicWind, Reset, Shift};
+use super::super::operator::{Cond, Case, When, Unless};
+use super::super::operator::{DefineSyntax, LetSyntax, LetrecSyntax, SyntaxRules};
+use super::super::operator::{DefineMacro, Defmacro, Gensym, MacroExpand1, MacroExpand};
+use super::super::operator::{Quasiquote, Unquote, UnquoteSplicing};
+use super::syntax::{syntax_rules, strip, gensym};
+use super::super::read::tokenize::Position;
+
+///Returns an error which has not been placed in the source yet. The
+///evaluator places it at the combination it was working on.
+fn error<T>(msg: &str) -> Result<T, RispError> {
+    Err(RispError::new(msg.to_strbuf(), None))
+}
+
+///Something that remains to be done with the value currently being
+///computed. Frames are pushed for everything that is not in tail position;
+///anything in tail position replaces the current expression instead, which
+///is what lets tail calls run in constant space.
+#[deriving(Clone)]
+pub enum Frame {
+    ///Collecting the values of a combination. The first value is the
+    ///procedure, followed by those of the operands evaluated so far, then
+    ///the operands still to go and the position of the combination.
+    Arguments(Vec<SExpr>, SExpr, Env, Option<Position>),
+    ///Choosing between the consequent and alternative of an if.
+    Branch(SExpr, Option<SExpr>, Env),
+    ///Evaluating the rest of a body, whose value is that of its last form.
+    Sequence(SExpr, Env),
+    ///Having evaluated the test of a cond clause, going on with its body if
+    ///it was true and with the rest of the clauses otherwise.
+    CondTest(SExpr, SExpr, Env),
+    ///Picking the clause of a case whose data include the value of the key.
+    CaseKey(SExpr, Env, Option<Position>),
+    ///Applying the value, the receiver of a => clause, to the saved value.
+    Receive(SExpr),
+    ///Evaluating the body of a when if the value was true, or of an unless
+    ///if it was false.
+    Guard(SExpr, Env, bool),
+    ///Binding the value to a symbol.
+    Definition(StrBuf, Env, Option<Position>),
+    ///Changing the value of a variable which is already bound, in whichever
+    ///frame binds it.
+    Assignment(DataType, Env, Option<Position>),
+    ///Binding the value, which must be a procedure, to a symbol as a macro
+    ///transformer.
+    MacroDefinition(StrBuf, Env, Option<Position>),
+    ///Evaluating the form a macro transformer returned in place of the use.
+    Expansion(Env),
+    ///Expanding the form a macro transformer returned, until it is no longer
+    ///a use of a macro bound in the environment.
+    Reexpand(Env),
+    ///Continuing an and with its remaining operands if the value was true.
+    Conjunction(SExpr, Env),
+    ///Continuing an or with its remaining operands if the value was false.
+    Disjunction(SExpr, Env),
+    ///The before thunk of a dynamic-wind has run; the winder goes into effect
+    ///and the thunk and after thunk come next.
+    WindEnter(SExpr, SExpr, SExpr),
+    ///The thunk of a dynamic-wind has run; its winder comes out of effect
+    ///before the after thunk runs.
+    WindExit(SExpr),
+    ///Throwing away the value of an after thunk for the one saved here.
+    Restore(SExpr),
+    ///Running the before and after thunks between where a continuation was
+    ///invoked and where it was captured, then passing it the saved value.
+    Rewind(Vec<(SExpr, Option<Rc<Winder>>)>, Rc<Captured>, SExpr),
+    ///The head of a quasiquoted list is done, and the tail at the given
+    ///nesting level comes next. Set if the head was spliced.
+    QuasiTail(SExpr, uint, Env, bool, Option<Position>),
+    ///Joining the head of a quasiquoted list, or the elements spliced in its
+    ///place, onto the tail.
+    QuasiJoin(SExpr, bool, Option<Position>),
+    ///Putting a nested quasiquote or unquote back around its operand.
+    QuasiWrap(OperatorType),
+    ///Going on with a map or for-each over the rest of the lists. The results
+    ///so far are kept in reverse, if they are being kept.
+    Mapping(SExpr, Vec<SExpr>, SExpr, bool),
+    ///The delimiter set up by reset. A shift captures the frames above the
+    ///nearest one.
+    Prompt,
+}
+
+///A continuation is a frame and everything that comes after it. Frames are
+///never changed once they're pushed, so a continuation can be shared.
+pub struct Continuation {
+    pub frame: Frame,
+    pub next: Option<Rc<Continuation>>
+}
+
+///A deep recursion leaves a long chain of continuations, which would
+///overflow the stack if each dropped the next in turn, so the chain is
+///unlinked here for as long as nothing else shares it.
+impl Drop for Continuation {
+    fn drop(&mut self) {
+        let mut next = self.next.take();
+        loop {
+            next = match next {
+                Some(cont)  => match rc::try_unwrap(cont) {
+                    Ok(mut cont)    => cont.next.take(),
+                    Err(_)          => None
+                },
+                None        => return
+            };
         }
     }
+}
 
-    let operator = match terms.get(0) {
-        &Operator(x) => match x {
-            Lambda  => return lambda(
-                match terms.get(1) {
-                    &Literal(ref ty) => ty,
-                    _   => return Err("FUCK".to_strbuf())
-                }),
-            _       => x,
-        },
-        _   => return Err("idgi yet".to_strbuf())
+///The before and after thunks of a dynamic-wind whose thunk is running, and
+///those of any dynamic-winds around it.
+pub struct Winder {
+    pub before: SExpr,
+    pub after: SExpr,
+    pub depth: uint,
+    pub next: Option<Rc<Winder>>
+}
+
+///A continuation captured by call/cc, with the winders in effect at the
+///time. Invoking it may happen any number of times.
+pub struct Captured {
+    pub cont: Option<Rc<Continuation>>,
+    pub winders: Option<Rc<Winder>>
+}
+
+///Continuations are only ever equal to themselves.
+impl Eq for Captured {
+    fn eq(&self, other: &Captured) -> bool {
+        (self as *Captured) == (other as *Captured)
+    }
+}
+
+///The frames a shift captured up to its reset, innermost first. Invoking
+///it pushes them back on top of the current continuation, inside a prompt
+///of their own, rather than replacing it.
+pub struct Subcontinuation {
+    pub frames: Vec<Frame>
+}
+
+impl Eq for Subcontinuation {
+    fn eq(&self, other: &Subcontinuation) -> bool {
+        (self as *Subcontinuation) == (other as *Subcontinuation)
+    }
+}
+
+///What the evaluator does next: evaluate an expression in an environment,
+///or hand a value to the topmost frame.
+enum State {
+    Evaluate(SExpr, Env),
+    Return(SExpr)
+}
+
+///Keeps everything an evaluation would otherwise keep on the Rust stack.
+struct Machine {
+    cont: Option<Rc<Continuation>>,
+    winders: Option<Rc<Winder>>,
+    pos: Option<Position>,
+    ///The environment the innermost combination being applied was
+    ///evaluated in.
+    env: Env
+}
+
+///Evaluates an expression. Literals evaluate to themselves and variables to
+///their values. A cell is a combination: special forms decide for themselves
+///which of their operands get evaluated, while every other operator is
+///applied to the values of all of its operands.
+pub fn eval(expr: &SExpr, environment: &Env) -> Result<SExpr, RispError> {
+    let mut machine = Machine {
+        cont: None,
+        winders: None,
+        pos: None,
+        env: environment.clone()
     };
+    let mut state = Evaluate(expr.clone(), environment.clone());
 
-    let mut operands: Vec<BasicType> = Vec::new();
-    for term in terms.tail().iter() {
-        match *term {
-            Operator(_) => return Err("Operating on an operator? Heresy!".to_strbuf()),
-            Literal(ref x)  => operands.push(x.clone()),
-            Variable(ref x) => {
-                match operator {
-                    Define  => operands.push(Symbol(x.clone())),
-                    _   => {
-                        let var = try!(lookup(x.as_slice(), environment));
-                        let val = match var {
-                            Data(x) => try!(eval(&vec!(x.clone()), environment)),
-                            _       => return Err("I can't handle this!".to_strbuf()),
+    loop {
+        let step = match state {
+            Evaluate(expr, env) => machine.eval(expr, env),
+            Return(value)       => match machine.cont.take() {
+                Some(cont)  => {
+                    machine.cont = cont.next.clone();
+                    machine.resume(&cont.frame, value)
+                }
+                None        => return Ok(value)
+            }
+        };
+
+        state = match step {
+            Ok(state)   => state,
+            Err(err)    => return Err(err.at(machine.pos))
+        };
+    }
+}
+
+impl Machine {
+    fn push(&mut self, frame: Frame) {
+        self.cont = Some(Rc::new(Continuation {
+            frame: frame,
+            next: self.cont.take()
+        }));
+    }
+
+    fn eval(&mut self, expr: SExpr, env: Env) -> Result<State, RispError> {
+        match expr {
+            Cons(head, operands, pos)   => {
+                if pos.is_some() {
+                    self.pos = pos;
+                }
+                match *head {
+                    Data(Operator(op))  => self.eval_operator(op, *operands, env, pos),
+                    Data(Variable(_)) | Data(Alias(_))  => {
+                        self.eval_keyword(*head, *operands, env, pos)
+                    }
+                    head    => self.next_operand(Vec::new(), cons(head, *operands), env, pos)
+                }
+            }
+            Data(data)  => match data {
+                Variable(_) | Alias(_)  => match resolve(&data, &env) {
+                    Ok(value)   => Ok(Return(value)),
+                    Err(msg)    => Err(RispError::new(msg, None))
+                },
+                //a built in operator anywhere but the head of a combination
+                //is a procedure like any other
+                Operator(op)    => match operator::arity(op) {
+                    Some(_) => Ok(Return(Data(Literal(Primitive(op))))),
+                    None    => Err(RispError::new(format!(
+                            "{} is a special form, not a procedure!", operator::to_str(op)).to_strbuf(), None))
+                },
+                atom    => Ok(Return(Data(atom)))
+            },
+            Nil     => error("Cannot evaluate an empty combination!")
+        }
+    }
+
+    ///Evaluates a combination headed by an identifier. If it names a macro,
+    ///the expansion is evaluated in its place; otherwise the identifier's
+    ///value is the procedure to apply.
+    fn eval_keyword(&mut self, head: SExpr, operands: SExpr, env: Env,
+                    pos: Option<Position>) -> Result<State, RispError> {
+        let value = match head {
+            Data(ref id)    => match resolve(id, &env) {
+                Ok(value)   => value,
+                Err(msg)    => return Err(RispError::new(msg, None))
+            },
+            _               => unreachable!()
+        };
+
+        match value {
+            Data(Literal(Syntax(rules)))    => {
+                match rules.expand(&cons(head, operands)) {
+                    Ok(expansion)   => Ok(Evaluate(expansion, env)),
+                    Err(msg)        => Err(RispError::new(msg, None))
+                }
+            }
+            Data(Literal(Macro(transformer)))   => {
+                //the transformer gets its operands as data, and returns data
+                let arguments: Vec<SExpr> = try!(operand_vec(&operands)).iter()
+                                                                         .map(to_data).collect();
+                self.push(Expansion(env));
+                self.apply(*transformer, arguments)
+            }
+            procedure   => self.next_operand(vec!(procedure), operands, env, pos)
+        }
+    }
+
+    ///Expands a form once if it's a use of a macro bound in `env`, or until
+    ///it no longer is if `repeat` is set. Anything else is returned as it is.
+    fn macroexpand(&mut self, form: SExpr, repeat: bool, env: Env) -> Result<State, RispError> {
+        let value = match form {
+            Cons(ref head, _, _)    => match **head {
+                Data(Variable(_)) | Data(Alias(_))  => match **head {
+                    Data(ref id)    => resolve(id, &env).ok(),
+                    _               => unreachable!()
+                },
+                _   => None
+            },
+            _   => None
+        };
+
+        match value {
+            Some(Data(Literal(Syntax(rules))))  => {
+                let expansion = match rules.expand(&form) {
+                    Ok(expansion)   => expansion,
+                    Err(msg)        => return Err(RispError::new(msg, None))
+                };
+                if repeat {
+                    self.macroexpand(expansion, true, env)
+                } else {
+                    Ok(Return(to_data(&strip(&expansion))))
+                }
+            }
+            Some(Data(Literal(Macro(transformer)))) => {
+                let arguments: Vec<SExpr> = match form {
+                    Cons(_, operands, _)    => try!(operand_vec(&*operands)).iter().map(to_data).collect(),
+                    _                       => unreachable!()
+                };
+                if repeat {
+                    self.push(Reexpand(env));
+                }
+                self.apply(*transformer, arguments)
+            }
+            _   => Ok(Return(to_data(&strip(&form))))
+        }
+    }
+
+    ///Evaluates a combination headed by an operator. Special forms are
+    ///handled here; anything else has its operands evaluated and is applied.
+    fn eval_operator(&mut self, op: OperatorType, operands: SExpr, env: Env,
+                     pos: Option<Position>) -> Result<State, RispError> {
+        match op {
+            Quote   => Ok(Return(try!(quote(&operands)))),
+            Lambda  => Ok(Return(try!(lambda(&operands, &env)))),
+            Define | DefineSyntax   => {
+                let (symbol, value) = try!(definition(&operands));
+                self.push(Definition(symbol, env.clone(), pos));
+                Ok(Evaluate(value, env))
+            }
+            Set     => {
+                let terms = try!(operand_vec(&operands));
+                if terms.len() != 2 || identifier_key(terms.get(0)).is_none() {
+                    return error("set! takes a symbol and a value!")
+                }
+                let id = match *terms.get(0) {
+                    Data(ref id)    => id.clone(),
+                    _               => unreachable!()
+                };
+                self.push(Assignment(id, env.clone(), pos));
+                Ok(Evaluate(terms.get(1).clone(), env))
+            }
+            SyntaxRules => Ok(Return(try!(syntax_rules(&operands, &env)))),
+            DefineMacro => {
+                let (target, rest) = match operands {
+                    Cons(target, rest, _)   => (*target, *rest),
+                    _   => return error("define-macro takes a name and a transformer!")
+                };
+                match target {
+                    //(define-macro (name . formals) body...)
+                    Cons(name, formals, _)  => {
+                        let key = match identifier_key(&*name) {
+                            Some(key)   => key,
+                            None        => return error("nonsymbol assignment detected!")
                         };
-                        operands.push(val);
+                        let transformer = try!(lambda(&cons(*formals, rest), &env));
+                        Ok(Return(define_macro(&env, key, transformer)))
+                    }
+                    //(define-macro name transformer)
+                    name    => {
+                        let (key, value) = try!(definition(&cons(name, rest)));
+                        self.push(MacroDefinition(key, env.clone(), pos));
+                        Ok(Evaluate(value, env))
+                    }
+                }
+            }
+            Defmacro    => {
+                let (key, rest) = match operands {
+                    Cons(name, rest, _) => match identifier_key(&*name) {
+                        Some(key)   => (key, *rest),
+                        None        => return error("nonsymbol assignment detected!")
+                    },
+                    _   => return error("defmacro takes a name, a lambda list and a body!")
+                };
+                let transformer = try!(lambda(&rest, &env));
+                Ok(Return(define_macro(&env, key, transformer)))
+            }
+            LetSyntax | LetrecSyntax    => {
+                let (bindings, body) = match operands {
+                    Cons(bindings, body, _) => (*bindings, *body),
+                    _   => return error("Expected syntax bindings and a body!")
+                };
+
+                let frame = Environment::new_frame(&env);
+                //macros bound by letrec-syntax can refer to one another
+                let scope = if op == LetrecSyntax { frame.clone() } else { env.clone() };
+                for binding in try!(operand_vec(&bindings)).iter() {
+                    let (keyword, spec) = try!(definition(binding));
+                    let rules = match spec {
+                        Cons(head, spec, _) => match *head {
+                            Data(Operator(SyntaxRules)) => try!(syntax_rules(&*spec, &scope)),
+                            _   => return error("Expected a syntax-rules form!")
+                        },
+                        _   => return error("Expected a syntax-rules form!")
+                    };
+                    frame.borrow_mut().variables.insert(keyword, rules);
+                }
+                self.sequence(body, frame)
+            }
+            Let     => Ok(Evaluate(try!(let_form(&operands)), env)),
+            LetStar => Ok(Evaluate(try!(let_star(&operands)), env)),
+            //the bindings are made one after another in a new frame, so each
+            //value can refer to any of the variables, as letrec* allows and
+            //letrec leaves unspecified
+            Letrec | LetrecStar => {
+                let (bindings, body) = match operands {
+                    Cons(bindings, body, _) => (*bindings, *body),
+                    _   => return error("Expected bindings and a body!")
+                };
+                if body == Nil {
+                    return error("Expected bindings and a body!")
+                }
+                let (names, inits) = try!(let_bindings(&bindings));
+                let definitions: Vec<SExpr> = names.move_iter().zip(inits.move_iter()).map(|(name, init)| {
+                    vec_to_list(vec!(Data(Operator(Define)), name, init))
+                }).collect();
+                let body = vec_to_improper_list(definitions, body);
+                let frame = Environment::new_frame(&env);
+                declare_definitions(&frame, &body);
+                self.sequence(body, frame)
+            }
+            Do      => Ok(Evaluate(try!(do_form(&operands)), env)),
+            Begin   => match operands {
+                Nil     => Ok(Return(Nil)),
+                body    => self.sequence(body, env)
+            },
+            If      => {
+                let terms = try!(operand_vec(&operands));
+                if terms.len() < 2 || terms.len() > 3 {
+                    return error("if takes a test, a consequent and maybe an alternative!")
+                }
+                let alternative = if terms.len() == 3 {
+                    Some(terms.get(2).clone())
+                } else {
+                    None
+                };
+                self.push(Branch(terms.get(1).clone(), alternative, env.clone()));
+                Ok(Evaluate(terms.get(0).clone(), env))
+            }
+            Reset   => {
+                self.push(Prompt);
+                self.sequence(operands, env)
+            }
+            Shift   => {
+                let (symbol, body) = match operands {
+                    Cons(symbol, body, _)   => match identifier_key(&*symbol) {
+                        Some(symbol)    => (symbol, *body),
+                        None            => return error("shift needs a symbol to bind!")
+                    },
+                    _   => return error("shift needs a symbol and a body!")
+                };
+
+                let mut frames = Vec::new();
+                loop {
+                    let cont = match self.cont.clone() {
+                        Some(cont)  => cont,
+                        None        => return error("shift without an enclosing reset!")
+                    };
+                    match cont.frame {
+                        Prompt  => break,
+                        _       => frames.push(cont.frame.clone())
                     }
+                    self.cont = cont.next.clone();
                 }
+
+                let subcontinuation = Rc::new(Subcontinuation { frames: frames });
+                let frame = Environment::new_frame(&env);
+                frame.borrow_mut().variables.insert(symbol,
+                        Data(Literal(basictype::Composable(subcontinuation))));
+                self.sequence(body, frame)
             }
+            Quasiquote  => match single(&operands) {
+                Some(template)  => self.quasiquote(template, 1, env, pos),
+                None            => error("quasiquote takes exactly one operand!")
+            },
+            Unquote | UnquoteSplicing   => error("Unquoted outside of a quasiquote!"),
+            Cond    => self.cond(operands, env),
+            Case    => match operands {
+                Cons(key, clauses, _)   => {
+                    self.push(CaseKey(*clauses, env.clone(), pos));
+                    Ok(Evaluate(*key, env))
+                }
+                _   => error("case takes a key and clauses!")
+            },
+            When | Unless   => match operands {
+                Cons(test, body, _) => {
+                    self.push(Guard(*body, env.clone(), op == When));
+                    Ok(Evaluate(*test, env))
+                }
+                _   => error("Expected a test and a body!")
+            },
+            And     => self.connective(operands, env, true),
+            Or      => self.connective(operands, env, false),
+            _       => self.next_operand(vec!(Data(Operator(op))), operands, env, pos)
         }
     }
 
-    match operator {
-        Add => add(&operands),
-        Mul => mul(&operands),
-        Define => def(&operands, environment),
-        _   => Err("idgi yet".to_strbuf())
+    ///Evaluates the next of a combination's operands, or applies the
+    ///procedure once they have all been evaluated.
+    fn next_operand(&mut self, done: Vec<SExpr>, rest: SExpr, env: Env,
+                    pos: Option<Position>) -> Result<State, RispError> {
+        match rest {
+            Cons(operand, rest, _)  => {
+                self.push(Arguments(done, *rest, env.clone(), pos));
+                Ok(Evaluate(*operand, env))
+            }
+            Nil     => {
+                let mut arguments = done;
+                let procedure = arguments.remove(0).unwrap();
+                self.env = env;
+                self.apply(procedure, arguments)
+            }
+            Data(_) => error("Expected a proper list of operands!")
+        }
+    }
+
+    ///Evaluates the operands of an and (or an or) up to the first one which
+    ///is false (or true), with the last one in tail position.
+    fn connective(&mut self, operands: SExpr, env: Env, conjunction: bool)
+                                                    -> Result<State, RispError> {
+        match operands {
+            Nil     => Ok(Return(Data(Literal(Boolean(conjunction))))),
+            Cons(operand, rest, _)  => {
+                if *rest != Nil {
+                    if conjunction {
+                        self.push(Conjunction(*rest, env.clone()));
+                    } else {
+                        self.push(Disjunction(*rest, env.clone()));
+                    }
+                }
+                Ok(Evaluate(*operand, env))
+            }
+            Data(_) => error("Expected a proper list of operands!")
+        }
     }
-}
 
+    ///Builds the structure a quasiquote template describes at nesting level
+    ///`depth`. Only what is unquoted at level one gets evaluated; nested
+    ///quasiquotes and unquotes are kept, with their levels adjusted. Errors
+    ///in splicing are placed at the quasiquote, at `pos`.
+    fn quasiquote(&mut self, template: SExpr, depth: uint, env: Env,
+                  pos: Option<Position>) -> Result<State, RispError> {
+        let (anterior, dorsal) = match template {
+            Cons(anterior, dorsal, _)   => (*anterior, *dorsal),
+            atom                        => return Ok(Return(strip(&atom)))
+        };
 
-pub fn add(terms: &Vec<BasicType>) -> Result<BasicType, StrBuf> {
-    let mut number_vec: Vec<NumericType> = Vec::new();
-    for term in terms.iter() {
-        match term {
-            &Number(ref ty) => number_vec.push(ty.clone()),
-            _           => return Err("foo".to_strbuf())
+        let keyword = match anterior {
+            Data(Operator(op)) if op == Quasiquote || op == Unquote || op == UnquoteSplicing => {
+                single(&dorsal).map(|operand| (op, operand))
+            }
+            _   => None
+        };
+        match keyword {
+            Some((Quasiquote, operand)) => {
+                self.push(QuasiWrap(Quasiquote));
+                return self.quasiquote(operand, depth + 1, env, pos)
+            }
+            Some((Unquote, operand)) if depth == 1  => return Ok(Evaluate(operand, env)),
+            Some((UnquoteSplicing, _)) if depth == 1    => {
+                return error("unquote-splicing must be an element of a list!")
+            }
+            Some((op, operand)) => {
+                self.push(QuasiWrap(op));
+                return self.quasiquote(operand, depth - 1, env, pos)
+            }
+            None    => { }
+        }
+
+        let splice = match anterior {
+            Cons(ref head, ref rest, _) if depth == 1 => match **head {
+                Data(Operator(UnquoteSplicing)) => single(&**rest),
+                _                               => None
+            },
+            _   => None
+        };
+        match splice {
+            Some(operand)   => {
+                self.push(QuasiTail(dorsal, depth, env.clone(), true, pos));
+                Ok(Evaluate(operand, env))
+            }
+            None            => {
+                self.push(QuasiTail(dorsal, depth, env.clone(), false, pos));
+                self.quasiquote(anterior, depth, env, pos)
+            }
         }
     }
 
-    //floating point will supercede integers
-    let mut float_flag = false;
-    for term in number_vec.iter() {
-        match term {
-            &Floating(_)    => float_flag = true,
-            &Integer(_)     => { }
-            _   => return Err("qux".to_strbuf())
+    ///Tries the clauses of a cond in order. An else clause always succeeds.
+    fn cond(&mut self, clauses: SExpr, env: Env) -> Result<State, RispError> {
+        let (clause, rest) = match clauses {
+            Nil                     => return Ok(Return(Nil)),
+            Cons(clause, rest, _)   => (*clause, *rest),
+            Data(_)                 => return error("Expected a proper list of clauses!")
+        };
+        match clause {
+            Cons(test, body, _) => {
+                if is_keyword(&*test, "else") {
+                    return self.sequence(*body, env)
+                }
+                self.push(CondTest(*body, rest, env.clone()));
+                Ok(Evaluate(*test, env))
+            }
+            _   => error("A cond clause needs a test!")
         }
     }
 
-    if float_flag == true {
-        let mut answer = 0.0;
-        for term in number_vec.iter() {
-            let augend = match *term {
-                Floating(x) => x,
-                Integer(x)  => x as f64,
-                _   => fail!("Unexpected argument!")
-            };
-            answer += augend;
+    ///Evaluates the body of a cond or case clause which was chosen because
+    ///of `value`. A body of the form (=> receiver) passes the value to the
+    ///receiver, and an empty one gives the value itself.
+    fn clause_body(&mut self, body: SExpr, value: SExpr, env: Env) -> Result<State, RispError> {
+        match body {
+            Nil     => Ok(Return(value)),
+            Cons(ref arrow, ref rest, _) if is_keyword(&**arrow, "=>")  => {
+                match single(&**rest) {
+                    Some(receiver)  => {
+                        self.push(Receive(value));
+                        Ok(Evaluate(receiver, env))
+                    }
+                    None    => error("=> must be followed by exactly one receiver!")
+                }
+            }
+            body    => self.sequence(body, env)
         }
-        return Ok(Number(Floating(answer)))
     }
 
-    let mut answer = 0;
-    for term in number_vec.iter() {
-        match *term {
-            Integer(x)  => answer += x,
-            _   => fail!("Unexpected argument!")
+    ///Evaluates a body in order, with its last form in tail position.
+    fn sequence(&mut self, body: SExpr, env: Env) -> Result<State, RispError> {
+        match body {
+            Cons(first, rest, _)    => {
+                if *rest != Nil {
+                    self.push(Sequence(*rest, env.clone()));
+                }
+                Ok(Evaluate(*first, env))
+            }
+            _   => error("Expected a nonempty body!")
+        }
+    }
+
+    ///Applies the procedure of a map or for-each to the next element of each
+    ///of the lists, or finishes once any one of them runs out.
+    fn map_next(&mut self, procedure: SExpr, lists: Vec<SExpr>, results: SExpr,
+                collect: bool) -> Result<State, RispError> {
+        let mut heads = Vec::new();
+        let mut tails = Vec::new();
+        for list in lists.iter() {
+            if !list::is_pair(list) {
+                return Ok(Return(if collect { list::reverse(&results).unwrap() } else { Nil }))
+            }
+            heads.push(list::car("", list).unwrap());
+            tails.push(list::cdr("", list).unwrap());
         }
+        self.push(Mapping(procedure.clone(), tails, results, collect));
+        self.apply(procedure, heads)
+    }
+
+    ///Hands a value to a frame which was waiting for it.
+    fn resume(&mut self, frame: &Frame, value: SExpr) -> Result<State, RispError> {
+        match *frame {
+            Arguments(ref done, ref rest, ref env, pos) => {
+                self.pos = pos;
+                let mut done = done.clone();
+                done.push(value);
+                self.next_operand(done, rest.clone(), env.clone(), pos)
+            }
+            Branch(ref consequent, ref alternative, ref env)    => {
+                if is_true(&value) {
+                    Ok(Evaluate(consequent.clone(), env.clone()))
+                } else {
+                    match *alternative {
+                        Some(ref alternative)   => Ok(Evaluate(alternative.clone(), env.clone())),
+                        None                    => Ok(Return(Nil))
+                    }
+                }
+            }
+            Sequence(ref rest, ref env) => self.sequence(rest.clone(), env.clone()),
+            CondTest(ref body, ref rest, ref env)   => {
+                if is_true(&value) {
+                    self.clause_body(body.clone(), value, env.clone())
+                } else {
+                    self.cond(rest.clone(), env.clone())
+                }
+            }
+            CaseKey(ref clauses, ref env, pos)  => {
+                self.pos = pos;
+                for clause in try!(operand_vec(clauses)).move_iter() {
+                    let (data, body) = match clause {
+                        Cons(data, body, _) => (*data, *body),
+                        _   => return error("A case clause needs a list of data!")
+                    };
+                    let chosen = is_keyword(&data, "else") ||
+                                 try!(operand_vec(&data)).iter().any(|datum| strip(datum) == value);
+                    if chosen {
+                        return self.clause_body(body, value, env.clone())
+                    }
+                }
+                Ok(Return(Nil))
+            }
+            Receive(ref saved)  => self.apply(value, vec!(saved.clone())),
+            Guard(ref body, ref env, when)  => {
+                if is_true(&value) == when {
+                    self.sequence(body.clone(), env.clone())
+                } else {
+                    Ok(Return(Nil))
+                }
+            }
+            Definition(ref symbol, ref env, pos)    => {
+                self.pos = pos;
+                env.borrow_mut().variables.insert(symbol.clone(), value);
+                Ok(Return(Data(Variable(symbol.clone()))))
+            }
+            Assignment(ref id, ref env, pos)    => {
+                self.pos = pos;
+                match assign(id, value, env) {
+                    Ok(())      => Ok(Return(Nil)),
+                    Err(msg)    => Err(RispError::new(msg, None))
+                }
+            }
+            MacroDefinition(ref symbol, ref env, pos)   => {
+                self.pos = pos;
+                match value {
+                    Data(Literal(Procedure(_))) => {
+                        Ok(Return(define_macro(env, symbol.clone(), value)))
+                    }
+                    _   => error("A macro transformer must be a procedure!")
+                }
+            }
+            Expansion(ref env)  => Ok(Evaluate(to_syntax(&value), env.clone())),
+            Reexpand(ref env)   => self.macroexpand(to_syntax(&value), true, env.clone()),
+            Conjunction(ref rest, ref env)  => {
+                if is_true(&value) {
+                    self.connective(rest.clone(), env.clone(), true)
+                } else {
+                    Ok(Return(value))
+                }
+            }
+            Disjunction(ref rest, ref env)  => {
+                if is_true(&value) {
+                    Ok(Return(value))
+                } else {
+                    self.connective(rest.clone(), env.clone(), false)
+                }
+            }
+            WindEnter(ref before, ref thunk, ref after) => {
+                self.winders = Some(Rc::new(Winder {
+                    before: before.clone(),
+                    after: after.clone(),
+                    depth: depth(&self.winders) + 1,
+                    next: self.winders.clone()
+                }));
+                self.push(WindExit(after.clone()));
+                self.apply(thunk.clone(), Vec::new())
+            }
+            WindExit(ref after) => {
+                let outer = match self.winders {
+                    Some(ref winder)    => winder.next.clone(),
+                    None                => None
+                };
+                self.winders = outer;
+                self.push(Restore(value));
+                self.apply(after.clone(), Vec::new())
+            }
+            Restore(ref saved)  => Ok(Return(saved.clone())),
+            Prompt              => Ok(Return(value)),
+            QuasiTail(ref dorsal, depth, ref env, splice, pos)  => {
+                self.push(QuasiJoin(value, splice, pos));
+                self.quasiquote(dorsal.clone(), depth, env.clone(), pos)
+            }
+            QuasiJoin(ref head, splice, pos)    => {
+                self.pos = pos;
+                if !splice {
+                    return Ok(Return(make_pair(head.clone(), value)))
+                }
+                match list_to_vec(head) {
+                    Ok(terms)   => Ok(Return(vec_to_data(terms, value))),
+                    Err(_)      => error("unquote-splicing needs a list to splice!")
+                }
+            }
+            QuasiWrap(op)       => Ok(Return(vec_to_data(vec!(Data(Operator(op)), value), Nil))),
+            Mapping(ref procedure, ref lists, ref results, collect) => {
+                let results = if collect { make_pair(value, results.clone()) } else { Nil };
+                self.map_next(procedure.clone(), lists.clone(), results, collect)
+            }
+            Rewind(ref steps, ref captured, ref saved)  => {
+                self.rewind(steps.clone(), captured.clone(), saved.clone())
+            }
+        }
+    }
+
+    ///Passes a value to a captured continuation, first running the after
+    ///thunks of the dynamic-winds being left and then the before thunks of
+    ///those being entered.
+    fn throw(&mut self, captured: Rc<Captured>, value: SExpr) -> Result<State, RispError> {
+        let mut exits = Vec::new();
+        let mut entries = Vec::new();
+
+        let mut from = self.winders.clone();
+        let mut to = captured.winders.clone();
+        while depth(&from) > depth(&to) {
+            let winder = from.unwrap();
+            exits.push((winder.after.clone(), winder.next.clone()));
+            from = winder.next.clone();
+        }
+        while depth(&to) > depth(&from) {
+            let winder = to.unwrap();
+            entries.push((winder.before.clone(), winder.next.clone()));
+            to = winder.next.clone();
+        }
+        while !same_winders(&from, &to) {
+            let (left, right) = (from.unwrap(), to.unwrap());
+            exits.push((left.after.clone(), left.next.clone()));
+            entries.push((right.before.clone(), right.next.clone()));
+            from = left.next.clone();
+            to = right.next.clone();
+        }
+
+        //entries were found innermost first, but have to run outermost first
+        entries.reverse();
+        exits.push_all_move(entries);
+        self.rewind(exits, captured, value)
+    }
+
+    ///Runs the next of the thunks needed to get to a captured continuation,
+    ///or installs it once there are none left.
+    fn rewind(&mut self, mut steps: Vec<(SExpr, Option<Rc<Winder>>)>, captured: Rc<Captured>,
+              value: SExpr) -> Result<State, RispError> {
+        if steps.len() == 0 {
+            self.cont = captured.cont.clone();
+            self.winders = captured.winders.clone();
+            return Ok(Return(value))
+        }
+
+        let (thunk, winders) = steps.remove(0).unwrap();
+        self.winders = winders;
+        self.push(Rewind(steps, captured, value));
+        self.apply(thunk, Vec::new())
     }
 
-    Ok(Number(Integer(answer)))
+    ///Applies a procedure to its arguments. Built in operators produce their
+    ///value straight away, while a closure's body is evaluated in a new frame.
+    fn apply(&mut self, procedure: SExpr, arguments: Vec<SExpr>) -> Result<State, RispError> {
+        match procedure {
+            Data(Operator(CallCC))  => {
+                if arguments.len() != 1 {
+                    return error("call/cc takes exactly one procedure!")
+                }
+                let captured = Rc::new(Captured {
+                    cont: self.cont.clone(),
+                    winders: self.winders.clone()
+                });
+                let receiver = arguments.move_iter().next().unwrap();
+                self.apply(receiver, vec!(Data(Literal(basictype::Continuation(captured)))))
+            }
+            Data(Operator(DynamicWind)) => {
+                if arguments.len() != 3 {
+                    return error("dynamic-wind takes a before thunk, a thunk and an after thunk!")
+                }
+                let before = arguments.get(0).clone();
+                self.push(WindEnter(before.clone(), arguments.get(1).clone(),
+                                    arguments.get(2).clone()));
+                self.apply(before, Vec::new())
+            }
+            Data(Operator(Map)) | Data(Operator(ForEach))   => {
+                if arguments.len() < 2 {
+                    return error("Expected a procedure and at least one list!")
+                }
+                let collect = procedure == Data(Operator(Map));
+                let mut lists = arguments;
+                let procedure = lists.remove(0).unwrap();
+                self.map_next(procedure, lists, Nil, collect)
+            }
+            Data(Operator(Apply))   => {
+                if arguments.len() < 2 {
+                    return error("apply takes a procedure, any arguments and a list of the rest!")
+                }
+                let mut arguments = arguments;
+                let procedure = arguments.remove(0).unwrap();
+                let spread = arguments.pop().unwrap();
+                match list_to_vec(&spread) {
+                    Ok(rest)    => arguments.push_all_move(rest),
+                    Err(_)      => return Err(RispError::new(format!(
+                            "apply needs a list as its last argument, got {}", spread).to_strbuf(), None))
+                }
+                self.apply(procedure, arguments)
+            }
+            //the expression is data, and is evaluated in tail position
+            Data(Operator(operator::Eval))  => match arguments.as_slice() {
+                [ref expr, Data(Literal(basictype::Environment(ref env)))]  => {
+                    Ok(Evaluate(to_syntax(expr), env.clone()))
+                }
+                _   => error("eval takes an expression and an environment!")
+            },
+            Data(Operator(InteractionEnvironment))  => {
+                if arguments.len() != 0 {
+                    return error("interaction-environment takes no arguments!")
+                }
+                Ok(Return(Data(Literal(basictype::Environment(outermost(&self.env))))))
+            }
+            //each of these is a new sandbox, which nothing else can see into.
+            //Its frames are collected along with those of the code using it
+            Data(Operator(SchemeReportEnvironment)) | Data(Operator(NullEnvironment))   => {
+                match arguments.as_slice() {
+                    [Data(Literal(Number(Integer(5))))] => { }
+                    _   => return error("Only version 5 of the report's environments is available!")
+                }
+                let registry = self.env.borrow().registry.clone();
+                let env = if procedure == Data(Operator(NullEnvironment)) {
+                    Environment::new_empty(&registry)
+                } else {
+                    Environment::new_standard(&registry)
+                };
+                Ok(Return(Data(Literal(basictype::Environment(env)))))
+            }
+            Data(Operator(Gensym))  => {
+                let prefix = match arguments.as_slice() {
+                    []  => "g".to_strbuf(),
+                    [Data(Literal(basictype::String(ref prefix)))]  => prefix.clone(),
+                    [Data(Variable(ref prefix))]                    => prefix.clone(),
+                    _   => return error("gensym takes at most a string or symbol prefix!")
+                };
+                Ok(Return(gensym(prefix.as_slice())))
+            }
+            Data(Operator(MacroExpand1)) | Data(Operator(MacroExpand))  => {
+                if arguments.len() != 1 {
+                    return error("macroexpand takes exactly one form!")
+                }
+                let repeat = procedure == Data(Operator(MacroExpand));
+                let env = self.env.clone();
+                self.macroexpand(to_syntax(arguments.get(0)), repeat, env)
+            }
+            Data(Operator(op))  => {
+                let result = match op {
+                    Constructor | Car | Cdr | Cxr(_) | List | SetCar | SetCdr | Null |
+                    IsPair | IsList | Length | Append | Reverse | ListTail | ListRef |
+                    ListCopy | IsEq | IsEqv | IsEqual   => list_operation(op, &arguments),
+                    Add | Sub | Mul | Div | Rem | Quotient | Modulo | FloorDiv |
+                    TruncateDiv | Gcd | Lcm | Abs | Min | Max | Exact | Inexact |
+                    Floor | Ceiling | Round | Truncate | Sqrt | ExactIntegerSqrt |
+                    Expt | Exp | Log | Sin | Cos | Tan | Asin | Acos | Atan | Square |
+                    IsNan | IsInfinite | IsFinite | IsZero | IsPositive | IsNegative |
+                    IsOdd | IsExact | IsInexact | BitwiseAnd | BitwiseOr | BitwiseXor |
+                    BitwiseNot | ArithmeticShift | BitCount | IsBitSet |
+                    IsEven  => arithmetic(op, &arguments),
+                    IsInteger | IsRational | IsReal | IsComplex | IsNumber  => {
+                        number_type(op, &arguments)
+                    }
+                    NumberToString | StringToNumber => conversion(op, &arguments),
+                    operator::Lt | operator::LtEq | operator::Eq | operator::NEq |
+                    operator::GtEq | operator::Gt   => comparison(op, &arguments),
+                    Print   => match arguments.as_slice() {
+                        [ref value] => {
+                            println!("{}", value);
+                            Ok(Nil)
+                        }
+                        _   => Err("print takes exactly one value!".to_strbuf())
+                    },
+                    _   => Err(format!("{} is a special form, not a procedure!",
+                                       operator::to_str(op)).to_strbuf())
+                };
+                match result {
+                    Ok(value)   => Ok(Return(value)),
+                    Err(msg)    => Err(RispError::new(msg, None))
+                }
+            }
+            Data(Literal(Primitive(op)))    => self.apply(Data(Operator(op)), arguments),
+            Data(Literal(Procedure(closure)))   => {
+                let frame = try!(bind(&*closure, arguments));
+                self.sequence(closure.body.clone(), frame)
+            }
+            Data(Literal(basictype::Continuation(captured)))    => {
+                if arguments.len() != 1 {
+                    return error("A continuation takes exactly one value!")
+                }
+                self.throw(captured, arguments.move_iter().next().unwrap())
+            }
+            Data(Literal(basictype::Composable(subcontinuation)))   => {
+                if arguments.len() != 1 {
+                    return error("A continuation takes exactly one value!")
+                }
+                self.push(Prompt);
+                for frame in subcontinuation.frames.iter().rev() {
+                    self.push(frame.clone());
+                }
+                Ok(Return(arguments.move_iter().next().unwrap()))
+            }
+            _   => Err(RispError::new(
+                    format!("Not a procedure: {}", procedure).to_strbuf(), None))
+        }
+    }
+}
+
+///The outermost frame enclosing `env`, which is the global environment of
+///whatever is running in it, whether that's the REPL's or a sandbox's.
+fn outermost(env: &Env) -> Env {
+    let mut env = env.clone();
+    loop {
+        let parent = env.borrow().parent.clone();
+        match parent {
+            Some(parent)    => env = parent,
+            None            => return env
+        }
+    }
+}
+
+///How many dynamic-winds are in effect.
+fn depth(winders: &Option<Rc<Winder>>) -> uint {
+    match *winders {
+        Some(ref winder)    => winder.depth,
+        None                => 0
+    }
 }
 
-pub fn mul(terms: &Vec<BasicType>) -> Result<BasicType, StrBuf> {
+///Whether two lists of winders are the very same list.
+fn same_winders(a: &Option<Rc<Winder>>, b: &Option<Rc<Winder>>) -> bool {
+    match (a, b) {
+        (&Some(ref a), &Some(ref b))    => (&**a as *Winder) == (&**b as *Winder),
+        (&None, &None)                  => true,
+        _                               => false
+    }
+}
+
+///Everything but #f counts as true.
+pub fn is_true(value: &SExpr) -> bool {
+    match *value {
+        Data(Literal(Boolean(false)))   => false,
+        _                               => true
+    }
+}
+
+///Whether an SExpr is an identifier written as `name`, such as the else of a
+///cond clause.
+fn is_keyword(expr: &SExpr, name: &str) -> bool {
+    match *expr {
+        Data(ref id)    => base_name(id) == Some(name.to_strbuf()),
+        _               => false
+    }
+}
+
+///Returns the only element of a one element list.
+fn single(list: &SExpr) -> Option<SExpr> {
+    match *list {
+        Cons(ref anterior, ref dorsal, _) if **dorsal == Nil    => Some(*anterior.clone()),
+        _                                                       => None
+    }
+}
+
+///Collects the operands of a special form.
+fn operand_vec(operands: &SExpr) -> Result<Vec<SExpr>, RispError> {
+    match list_to_vec(operands) {
+        Ok(terms)   => Ok(terms),
+        Err(msg)    => Err(RispError::new(msg, None))
+    }
+}
+
+///Makes the frame for an application of a closure, binding each parameter
+///to its argument and any rest parameter to a list of the leftovers.
+fn bind(closure: &Closure, arguments: Vec<SExpr>) -> Result<Env, RispError> {
+    let arity = closure.params.len();
+    match closure.rest {
+        Some(_) if arguments.len() >= arity => { }
+        None if arguments.len() == arity    => { }
+        Some(_) => return Err(RispError::new(format!(
+                "Expected at least {} arguments, got {}", arity, arguments.len()).to_strbuf(), None)),
+        None    => return Err(RispError::new(format!(
+                "Expected {} arguments, got {}", arity, arguments.len()).to_strbuf(), None))
+    }
+
+    let frame = Environment::new_frame(&closure.env);
+    {
+        let mut bindings = frame.borrow_mut();
+        let mut arguments = arguments.move_iter();
+        for param in closure.params.iter() {
+            bindings.variables.insert(param.clone(), arguments.next().unwrap());
+        }
+        match closure.rest {
+            Some(ref rest)  => {
+                bindings.variables.insert(rest.clone(), vec_to_data(arguments.collect(), Nil));
+            }
+            None            => { }
+        }
+    }
+    declare_definitions(&frame, &closure.body);
+    Ok(frame)
+}
+
+///Declares the variables defined at the top level of a body in the frame
+///it's about to run in. The definitions then work like the bindings of a
+///letrec*: each variable is in scope throughout the body, shadowing any
+///outer one, but can't be used until its definition has been evaluated.
+fn declare_definitions(frame: &Env, body: &SExpr) {
+    for form in list_to_vec(body).unwrap_or(Vec::new()).iter() {
+        let name = match *form {
+            Cons(ref head, ref operands, _) if **head == Data(Operator(Define))  => {
+                match **operands {
+                    Cons(ref name, _, _)    => identifier_key(&**name),
+                    _                       => None
+                }
+            }
+            _   => None
+        };
+        match name {
+            Some(key)   => { frame.borrow_mut().declared.insert(key); }
+            None        => { }
+        }
+    }
+}
+
+///Applies one of the operators on pairs and lists. Consing onto anything but
+///a list makes an improper list, which prints in dotted notation.
+pub fn list_operation(op: OperatorType, terms: &Vec<SExpr>) -> Result<SExpr, StrBuf> {
+    match op {
+        List    => return Ok(vec_to_data(terms.clone(), Nil)),
+        Append  => return list::append(terms),
+        _       => { }
+    }
+
+    let takes_two = match op {
+        Constructor | SetCar | SetCdr | ListTail | ListRef | IsEq | IsEqv |
+        IsEqual => true,
+        _       => false
+    };
+    if takes_two && terms.len() != 2 {
+        return Err(format!("{} takes exactly two arguments!", operator::to_str(op)).to_strbuf())
+    } else if !takes_two && terms.len() != 1 {
+        return Err(format!("{} takes exactly one argument!", operator::to_str(op)).to_strbuf())
+    }
+
+    let x = terms.get(0);
+    match op {
+        Constructor => Ok(make_pair(x.clone(), terms.get(1).clone())),
+        Car         => list::car("car", x),
+        Cdr         => list::cdr("cdr", x),
+        Cxr(index)  => list::cxr(index, x),
+        SetCar      => list::set_car(x, terms.get(1).clone()).map(|_| Nil),
+        SetCdr      => list::set_cdr(x, terms.get(1).clone()).map(|_| Nil),
+        Null        => Ok(Data(Literal(Boolean(*x == Nil)))),
+        IsPair      => Ok(Data(Literal(Boolean(list::is_pair(x))))),
+        IsList      => Ok(Data(Literal(Boolean(list::is_list(x))))),
+        IsEq | IsEqv    => Ok(Data(Literal(Boolean(list::is_eqv(x, terms.get(1)))))),
+        IsEqual     => Ok(Data(Literal(Boolean(list::is_equal(x, terms.get(1)))))),
+        Length      => list::length(x),
+        Reverse     => list::reverse(x),
+        ListTail    => list::list_tail("list-tail", x, terms.get(1)),
+        ListRef     => list::list_ref(x, terms.get(1)),
+        _           => list::list_copy(x)
+    }
+}
+
+///Collects the numeric values of the arguments given to an arithmetic
+///operator.
+fn numbers(terms: &Vec<SExpr>) -> Result<Vec<NumericType>, StrBuf> {
     let mut number_vec: Vec<NumericType> = Vec::new();
     for term in terms.iter() {
-        match term {
-            &Number(ref ty) => number_vec.push(ty.clone()),
-            _           => return Err("foo".to_strbuf())
+        match *term {
+            Data(Literal(Number(ref ty)))   => number_vec.push(ty.clone()),
+            _   => return Err(format!("Expected a number, got {}", term).to_strbuf())
         }
     }
+    Ok(number_vec)
+}
 
-    //floating point will supercede integers
-    let mut float_flag = false;
-    for term in number_vec.iter() {
-        match term {
-            &Floating(_)    => float_flag = true,
-            &Integer(_)     => { }
-            _   => return Err("qux".to_strbuf())
+///Applies one of the arithmetic operators. Numbers of different kinds are
+///converted to the more general kind first, so exactness is kept for as
+///long as every argument is exact.
+pub fn arithmetic(op: OperatorType, terms: &Vec<SExpr>) -> Result<SExpr, StrBuf> {
+    let number_vec = try!(numbers(terms));
+
+    let answer = match op {
+        Add => number_vec.iter().fold(Integer(0), |sum, x| number::add(&sum, x)),
+        Mul => number_vec.iter().fold(Integer(1), |product, x| number::mul(&product, x)),
+        Sub | Div   => {
+            if number_vec.len() == 0 {
+                return Err(format!("{} takes at least one argument!",
+                                   operator::to_str(op)).to_strbuf())
+            }
+            //with a single argument, (- x) negates x and (/ x) is (/ 1 x)
+            let (first, rest) = match number_vec.len() {
+                1 if op == Sub  => (number::negate(number_vec.get(0)), number_vec.slice_from(1)),
+                1               => (Integer(1), number_vec.as_slice()),
+                _               => (number_vec.get(0).clone(), number_vec.slice_from(1))
+            };
+            let mut answer = first;
+            for x in rest.iter() {
+                answer = if op == Sub {
+                    number::sub(&answer, x)
+                } else {
+                    try!(number::div(&answer, x))
+                };
+            }
+            answer
         }
+        Rem | Quotient | Modulo => {
+            let (a, b) = try!(two(op, &number_vec));
+            try!(match op {
+                Rem         => number::remainder(a, b),
+                Quotient    => number::quotient(a, b),
+                _           => number::modulo(a, b)
+            })
+        }
+        //with no multiple values to return, the quotient and remainder (or
+        //the root and what's left over) come back as a list of the two
+        FloorDiv | TruncateDiv | ExactIntegerSqrt   => {
+            let (q, r) = if op == ExactIntegerSqrt {
+                try!(number::exact_integer_sqrt(try!(one(op, &number_vec))))
+            } else {
+                let (a, b) = try!(two(op, &number_vec));
+                try!(number::divide(a, b, op == FloorDiv))
+            };
+            return Ok(vec_to_data(vec!(Data(Literal(Number(q))), Data(Literal(Number(r)))), Nil))
+        }
+        Expt    => {
+            let (a, b) = try!(two(op, &number_vec));
+            try!(number::expt(a, b))
+        }
+        //log takes an optional base, and atan an optional x coordinate
+        Log | Atan if number_vec.len() == 2 => {
+            let (a, b) = try!(two(op, &number_vec));
+            try!(if op == Log { number::log_base(a, b) } else { number::atan2(a, b) })
+        }
+        Gcd | Lcm   => {
+            let mut answer = Integer(if op == Gcd { 0 } else { 1 });
+            for x in number_vec.iter() {
+                answer = try!(if op == Gcd { number::gcd(&answer, x) } else { number::lcm(&answer, x) });
+            }
+            answer
+        }
+        BitwiseAnd | BitwiseOr | BitwiseXor => {
+            let mut answer = Integer(if op == BitwiseAnd { -1 } else { 0 });
+            for x in number_vec.iter() {
+                answer = try!(match op {
+                    BitwiseAnd  => number::bitwise_and(&answer, x),
+                    BitwiseOr   => number::bitwise_or(&answer, x),
+                    _           => number::bitwise_xor(&answer, x)
+                });
+            }
+            answer
+        }
+        BitwiseNot  => try!(number::bitwise_not(try!(one(op, &number_vec)))),
+        BitCount    => try!(number::bit_count(try!(one(op, &number_vec)))),
+        ArithmeticShift => {
+            let (a, b) = try!(two(op, &number_vec));
+            try!(number::arithmetic_shift(a, b))
+        }
+        IsBitSet    => {
+            let (index, a) = try!(two(op, &number_vec));
+            return Ok(Data(Literal(Boolean(try!(number::is_bit_set(index, a))))))
+        }
+        Min | Max   => {
+            if number_vec.len() == 0 {
+                return Err(format!("{} takes at least one argument!",
+                                   operator::to_str(op)).to_strbuf())
+            }
+            let mut answer = number_vec.get(0).clone();
+            for x in number_vec.iter() {
+                answer = try!(number::extremum(&answer, x, op == Max));
+            }
+            answer
+        }
+        Abs | Exact | Inexact | Floor | Ceiling | Round | Truncate | Sqrt | Exp |
+        Log | Sin | Cos | Tan | Asin | Acos | Atan | Square => {
+            let a = try!(one(op, &number_vec));
+            try!(match op {
+                Sqrt    => number::sqrt(a),
+                Exp     => Ok(number::exp(a)),
+                Log     => Ok(number::log(a)),
+                Sin     => Ok(number::sin(a)),
+                Cos     => Ok(number::cos(a)),
+                Tan     => Ok(number::tan(a)),
+                Asin    => Ok(number::asin(a)),
+                Acos    => Ok(number::acos(a)),
+                Atan    => Ok(number::atan(a)),
+                Square  => Ok(number::mul(a, a)),
+                Abs     => number::abs(a),
+                Exact   => number::exact(a),
+                Inexact => Ok(number::inexact(a)),
+                Floor   => number::floor(a),
+                Ceiling => number::ceiling(a),
+                Round   => number::round(a),
+                _       => number::truncate(a)
+            })
+        }
+        IsEven | IsOdd | IsNan | IsInfinite | IsFinite | IsZero | IsPositive |
+        IsNegative | IsExact | IsInexact    => {
+            let a = try!(one(op, &number_vec));
+            let holds = match op {
+                IsNan       => number::is_nan(a),
+                IsInfinite  => number::is_infinite(a),
+                IsFinite    => !number::is_nan(a) && !number::is_infinite(a),
+                IsZero      => a.is_zero(),
+                IsPositive  => try!(number::compare(a, &Integer(0))) == Some(Greater),
+                IsNegative  => try!(number::compare(a, &Integer(0))) == Some(Less),
+                IsExact     => a.is_exact(),
+                IsInexact   => !a.is_exact(),
+                IsOdd       => !try!(number::is_even(a)),
+                _           => try!(number::is_even(a))
+            };
+            return Ok(Data(Literal(Boolean(holds))))
+        }
+        _   => unreachable!()
+    };
+
+    Ok(Data(Literal(Number(answer))))
+}
+
+///The only argument given to an operator which takes exactly one.
+fn one<'a>(op: OperatorType, number_vec: &'a Vec<NumericType>) -> Result<&'a NumericType, StrBuf> {
+    if number_vec.len() != 1 {
+        return Err(format!("{} takes exactly one argument!", operator::to_str(op)).to_strbuf())
     }
+    Ok(number_vec.get(0))
+}
 
-    if float_flag == true {
-        let mut answer = 1.0;
-        for term in number_vec.iter() {
-            let augend = match *term {
-                Floating(x) => x,
-                Integer(x)  => x as f64,
-                _   => fail!("Unexpected argument!")
-            };
-            answer *= augend;
+///Both arguments given to an operator which takes exactly two.
+fn two<'a>(op: OperatorType, number_vec: &'a Vec<NumericType>)
+                                -> Result<(&'a NumericType, &'a NumericType), StrBuf> {
+    if number_vec.len() != 2 {
+        return Err(format!("{} takes exactly two arguments!", operator::to_str(op)).to_strbuf())
+    }
+    Ok((number_vec.get(0), number_vec.get(1)))
+}
+
+///Applies one of the predicates on the kind of a number. Unlike the other
+///numeric operators, these take anything, and are false for non-numbers.
+pub fn number_type(op: OperatorType, terms: &Vec<SExpr>) -> Result<SExpr, StrBuf> {
+    if terms.len() != 1 {
+        return Err(format!("{} takes exactly one argument!", operator::to_str(op)).to_strbuf())
+    }
+    let holds = match *terms.get(0) {
+        Data(Literal(Number(ref a)))    => match op {
+            IsInteger   => a.is_integer(),
+            IsRational  => a.is_rational(),
+            IsReal      => a.is_real(),
+            _           => true
+        },
+        _   => false
+    };
+    Ok(Data(Literal(Boolean(holds))))
+}
+
+///Converts a number to a string or back, in an optional radix of 2, 8, 10
+///or 16. A string which doesn't read as a number converts to #f.
+pub fn conversion(op: OperatorType, terms: &Vec<SExpr>) -> Result<SExpr, StrBuf> {
+    let radix = match terms.len() {
+        1   => 10,
+        2   => match *terms.get(1) {
+            Data(Literal(Number(Integer(radix)))) if radix == 2 || radix == 8 ||
+                                                     radix == 10 || radix == 16 => radix as uint,
+            _   => return Err(format!("Expected a radix of 2, 8, 10 or 16, got {}",
+                                      terms.get(1)).to_strbuf())
+        },
+        _   => return Err(format!("{} takes a value and maybe a radix!",
+                                  operator::to_str(op)).to_strbuf())
+    };
+
+    match (op, terms.get(0)) {
+        (NumberToString, &Data(Literal(Number(ref a))))  => {
+            Ok(Data(Literal(basictype::String(try!(number::to_str_radix(a, radix))))))
         }
-        return Ok(Number(Floating(answer)))
+        (StringToNumber, &Data(Literal(basictype::String(ref s))))  => {
+            match number::parse_radix(s.as_slice(), radix) {
+                Some(a) => Ok(Data(Literal(Number(a)))),
+                None    => Ok(Data(Literal(Boolean(false))))
+            }
+        }
+        (NumberToString, term)  => Err(format!("Expected a number, got {}", term).to_strbuf()),
+        (_, term)               => Err(format!("Expected a string, got {}", term).to_strbuf())
     }
+}
 
-    let mut answer = 1;
-    for term in number_vec.iter() {
-        match *term {
-            Integer(x)  => answer *= x,
-            _   => fail!("Unexpected argument!")
+///Applies one of the numeric comparisons, which holds if it holds between
+///each argument and the next.
+pub fn comparison(op: OperatorType, terms: &Vec<SExpr>) -> Result<SExpr, StrBuf> {
+    let number_vec = try!(numbers(terms));
+
+    for pair in number_vec.as_slice().windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let holds = match op {
+            operator::Eq    => number::equal(a, b),
+            operator::NEq   => !number::equal(a, b),
+            _   => match try!(number::compare(a, b)) {
+                Some(order) => match op {
+                    operator::Lt    => order == Less,
+                    operator::LtEq  => order != Greater,
+                    operator::GtEq  => order != Less,
+                    operator::Gt    => order == Greater,
+                    _               => unreachable!()
+                },
+                None        => false
+            }
+        };
+        if !holds {
+            return Ok(Data(Literal(Boolean(false))))
         }
     }
 
-    Ok(Number(Integer(answer)))
+    Ok(Data(Literal(Boolean(true))))
+}
+
+///Returns the operand of a quote unevaluated, as data made of fresh pairs.
+///Identifiers renamed by a macro expansion are quoted as they were written.
+pub fn quote(operands: &SExpr) -> Result<SExpr, RispError> {
+    match *operands {
+        Cons(ref datum, ref rest, _) if **rest == Nil   => Ok(to_data(&strip(&**datum))),
+        _   => error("quote takes exactly one operand!")
+    }
+}
+
+///Splits the bindings of a let form into the identifiers being bound and
+///the expressions for their values.
+fn let_bindings(bindings: &SExpr) -> Result<(Vec<SExpr>, Vec<SExpr>), RispError> {
+    let mut names = Vec::new();
+    let mut inits = Vec::new();
+    for binding in try!(operand_vec(bindings)).move_iter() {
+        let terms = try!(operand_vec(&binding));
+        if terms.len() != 2 || identifier_key(terms.get(0)).is_none() {
+            return Err(RispError::new(
+                    format!("Expected a binding of a symbol to a value, got {}", binding).to_strbuf(), None))
+        }
+        names.push(terms.get(0).clone());
+        inits.push(terms.get(1).clone());
+    }
+    Ok((names, inits))
 }
 
-pub fn def(terms: &Vec<BasicType>, mut env: &mut Environment) -> Result<BasicType, StrBuf> {
-    assert!(terms.len() >= 2);
-    let symbol = match terms.as_slice()[0] {
-        Symbol(ref x)   => x.clone(),
-        _   => return Err("nonsymbol assignment detected!".to_strbuf())
+///Rewrites a let as the application of a lambda to the values of its
+///bindings. A named let also binds its name to the lambda, within the body,
+///so that the body can loop by calling it.
+fn let_form(operands: &SExpr) -> Result<SExpr, RispError> {
+    let (name, bindings, body) = match *operands {
+        Cons(ref name, ref rest, _) if identifier_key(&**name).is_some()  => match **rest {
+            Cons(ref bindings, ref body, _) => (Some(*name.clone()), *bindings.clone(), *body.clone()),
+            _   => return error("A named let needs bindings and a body!")
+        },
+        Cons(ref bindings, ref body, _) => (None, *bindings.clone(), *body.clone()),
+        _   => return error("let needs bindings and a body!")
     };
+    if body == Nil {
+        return error("let needs bindings and a body!")
+    }
 
-    let value = terms.as_slice()[1].clone();
+    let (names, inits) = try!(let_bindings(&bindings));
+    let procedure = cons(Data(Operator(Lambda)), cons(vec_to_list(names), body));
+    let procedure = match name {
+        //(letrec ((name procedure)) name)
+        Some(name)  => vec_to_list(vec!(Data(Operator(Letrec)),
+                                        vec_to_list(vec!(vec_to_list(vec!(name.clone(), procedure)))),
+                                        name)),
+        None        => procedure
+    };
+    Ok(cons(procedure, vec_to_list(inits)))
+}
 
-    env.variables.insert(symbol.clone(), Data(Literal(value)));
+///Rewrites a let* as a let of its first binding around a let* of the rest,
+///so that each value can refer to the variables bound before it.
+fn let_star(operands: &SExpr) -> Result<SExpr, RispError> {
+    let (bindings, body) = match *operands {
+        Cons(ref bindings, ref body, _) => (try!(operand_vec(&**bindings)), *body.clone()),
+        _   => return error("let* needs bindings and a body!")
+    };
+    if bindings.len() < 2 {
+        return Ok(cons(Data(Operator(Let)), operands.clone()))
+    }
 
-    Ok(Symbol(symbol))
+    let mut bindings = bindings.move_iter();
+    let first = vec_to_list(vec!(bindings.next().unwrap()));
+    let rest = cons(Data(Operator(LetStar)), cons(vec_to_list(bindings.collect()), body));
+    Ok(vec_to_list(vec!(Data(Operator(Let)), first, rest)))
 }
 
-pub fn lambda(terms: &BasicType) -> Result<BasicType, StrBuf> {
-    //I don't know, man.
-    match *terms {
-        Procedure(_)    => Ok(terms.clone()),
-        _   => Err("not a lambda!".to_strbuf()),
+///Rewrites a do loop as a named let. The name is a fresh symbol, so that
+///nothing in the loop can call it by accident. A variable without a step
+///keeps its value from one iteration to the next.
+fn do_form(operands: &SExpr) -> Result<SExpr, RispError> {
+    let terms = try!(operand_vec(operands));
+    if terms.len() < 2 {
+        return error("do takes variables, a test and a body!")
+    }
+
+    let mut bindings = Vec::new();
+    let mut steps = Vec::new();
+    for spec in try!(operand_vec(terms.get(0))).move_iter() {
+        let parts = try!(operand_vec(&spec));
+        if parts.len() < 2 || parts.len() > 3 || identifier_key(parts.get(0)).is_none() {
+            return Err(RispError::new(format!(
+                    "Expected a do variable with a value and maybe a step, got {}", spec).to_strbuf(), None))
+        }
+        steps.push(parts.get(if parts.len() == 3 { 2 } else { 0 }).clone());
+        bindings.push(vec_to_list(vec!(parts.get(0).clone(), parts.get(1).clone())));
     }
+    let (test, exprs) = match *terms.get(1) {
+        Cons(ref test, ref exprs, _)    => (*test.clone(), *exprs.clone()),
+        _   => return error("do needs a test, and maybe expressions for its value!")
+    };
+
+    //(if test (begin exprs...) (begin commands... (name steps...)))
+    let name = gensym("do");
+    let again = cons(name.clone(), vec_to_list(steps));
+    let commands = Vec::from_slice(terms.slice_from(2));
+    let body = vec_to_list(vec!(Data(Operator(If)), test,
+            cons(Data(Operator(Begin)), exprs),
+            cons(Data(Operator(Begin)), vec_to_improper_list(commands, vec_to_list(vec!(again))))));
+    Ok(vec_to_list(vec!(Data(Operator(Let)), name, vec_to_list(bindings), body)))
+}
+
+///Binds a transformer as a macro in `env`, returning the macro's name.
+fn define_macro(env: &Env, key: StrBuf, transformer: SExpr) -> SExpr {
+    env.borrow_mut().variables.insert(key.clone(), Data(Literal(Macro(box transformer))));
+    Data(Variable(key))
+}
+
+///Splits a define into the symbol being bound and the expression for its
+///value.
+pub fn definition(operands: &SExpr) -> Result<(StrBuf, SExpr), RispError> {
+    let terms = try!(operand_vec(operands));
+    if terms.len() != 2 {
+        return error("define takes a symbol and a value!")
+    }
+
+    match identifier_key(terms.get(0)) {
+        Some(x) => Ok((x, terms.get(1).clone())),
+        None    => error("nonsymbol assignment detected!")
+    }
+}
+
+///Makes a closure over the current environment. The parameters may be a
+///list of symbols, a dotted list whose last symbol collects any remaining
+///arguments, or a single symbol which collects all of them.
+pub fn lambda(operands: &SExpr, env: &Env) -> Result<SExpr, RispError> {
+    let (formals, body) = match *operands {
+        Cons(ref formals, ref body, _)  => (&**formals, &**body),
+        _   => return error("Improper lambda expression!")
+    };
+
+    let mut params = Vec::new();
+    let mut rest = None;
+    let mut formal = formals;
+    loop {
+        match *formal {
+            Cons(ref param, ref dorsal, _)  => {
+                match identifier_key(&**param) {
+                    Some(name)  => params.push(name),
+                    None        => return error("Lambda parameters must be symbols!")
+                }
+                formal = &**dorsal;
+            }
+            Nil     => break,
+            _       => match identifier_key(formal) {
+                Some(name)  => {
+                    rest = Some(name);
+                    break
+                }
+                None        => return error("Lambda parameters must be symbols!")
+            }
+        }
+    }
+
+    match list_to_vec(body) {
+        Ok(ref body) if body.len() == 0 => return error("Lambda needs a body!"),
+        Ok(_)       => { }
+        Err(msg)    => return Err(RispError::new(msg, None))
+    }
+
+    Ok(Data(Literal(Procedure(Rc::new(Closure {
+        params: params,
+        rest: rest,
+        body: body.clone(),
+        env: env.clone()
+    })))))
 }
@@ -1,5 +1,6 @@
-pub use self::eval::eval;
+pub use self::eval::{eval, Captured, Subcontinuation};
 
 pub mod eval;
+pub mod syntax;
 
 
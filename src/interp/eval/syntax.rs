@@ -0,0 +1,419 @@
+//! Macros defined with syntax-rules.
+
+extern crate collections;
+
+use std::rc::Rc;
+use std::sync::atomics::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+use self::collections::HashMap;
+
+use super::super::{SExpr, Cons, Data, Literal, Variable, Alias};
+use super::super::{Env, Renaming, RispError};
+use super::super::{identifier_key, base_name, list_to_vec, vec_to_improper_list};
+use super::super::basictype::Syntax;
+
+///Counts renamings and gensyms, so that each gets a name of its own.
+static mut RENAMINGS: AtomicUint = INIT_ATOMIC_UINT;
+
+///Returns a number which hasn't been used for a renaming or gensym before.
+pub fn fresh_count() -> uint {
+    unsafe { RENAMINGS.fetch_add(1, SeqCst) + 1 }
+}
+
+///Makes a symbol which can't be read from source, so it can't clash with
+///any symbol already in use. It's printed with a #: prefix.
+pub fn gensym(prefix: &str) -> SExpr {
+    Data(Variable(format!("{}{}{}", "#:", prefix, fresh_count()).to_strbuf()))
+}
+
+///A macro made by syntax-rules. It has the identifier which stands for an
+///ellipsis in its patterns, the literals which patterns match by name, the
+///pattern and template of each rule, and the environment the macro was made
+///in, which is where the free identifiers of its templates are looked up.
+pub struct Rules {
+    pub ellipsis: StrBuf,
+    pub literals: Vec<StrBuf>,
+    pub rules: Vec<(SExpr, SExpr)>,
+    pub env: Env
+}
+
+///Macros are only ever equal to themselves.
+impl Eq for Rules {
+    fn eq(&self, other: &Rules) -> bool {
+        (self as *Rules) == (other as *Rules)
+    }
+}
+
+///What a pattern variable matched. Beneath an ellipsis, there's one match
+///per repetition.
+#[deriving(Clone)]
+enum Binding {
+    One(SExpr),
+    Many(Vec<Binding>)
+}
+
+///Makes a macro from the operands of a syntax-rules form: an optional
+///custom ellipsis, a list of literals, then the rules.
+pub fn syntax_rules(operands: &SExpr, env: &Env) -> Result<SExpr, RispError> {
+    let mut terms = match list_to_vec(operands) {
+        Ok(terms)   => terms,
+        Err(msg)    => return Err(RispError::new(msg, None))
+    };
+    if terms.len() == 0 {
+        return Err(RispError::new("syntax-rules needs a list of literals!".to_strbuf(), None))
+    }
+
+    let ellipsis = match identifier_key(terms.get(0)) {
+        Some(ellipsis)  => {
+            terms.remove(0);
+            ellipsis
+        }
+        None    => "...".to_strbuf()
+    };
+    if terms.len() == 0 {
+        return Err(RispError::new("syntax-rules needs a list of literals!".to_strbuf(), None))
+    }
+
+    let mut literals = Vec::new();
+    for literal in try!(operand_list(terms.get(0))).iter() {
+        match identifier_key(literal) {
+            Some(key)   => literals.push(key),
+            None        => return Err(RispError::new(
+                    format!("Not an identifier: {}", literal).to_strbuf(), None))
+        }
+    }
+
+    let mut rules = Vec::new();
+    for rule in terms.slice_from(1).iter() {
+        let rule = try!(operand_list(rule));
+        if rule.len() != 2 {
+            return Err(RispError::new(
+                    "A syntax rule is a pattern and a template!".to_strbuf(), None))
+        }
+        match *rule.get(0) {
+            Cons(..)    => { }
+            _   => return Err(RispError::new(
+                    "A syntax rule's pattern must be a list!".to_strbuf(), None))
+        }
+        rules.push((rule.get(0).clone(), rule.get(1).clone()));
+    }
+
+    Ok(Data(Literal(Syntax(Rc::new(Rules {
+        ellipsis: ellipsis,
+        literals: literals,
+        rules: rules,
+        env: env.clone()
+    })))))
+}
+
+fn operand_list(list: &SExpr) -> Result<Vec<SExpr>, RispError> {
+    match list_to_vec(list) {
+        Ok(terms)   => Ok(terms),
+        Err(msg)    => Err(RispError::new(msg, None))
+    }
+}
+
+///Splits a list into its elements and whatever ends it, which is Nil for a
+///proper list. An atom has no elements and is its own ending.
+fn elements(list: &SExpr) -> (Vec<SExpr>, SExpr) {
+    let mut items = Vec::new();
+    let mut rest = list;
+    loop {
+        match *rest {
+            Cons(ref anterior, ref dorsal, _)   => {
+                items.push(*anterior.clone());
+                rest = &**dorsal;
+            }
+            _   => return (items, rest.clone())
+        }
+    }
+}
+
+///The dorsal region of a cell, or the SExpr itself if it isn't one.
+fn rest<'a>(form: &'a SExpr) -> &'a SExpr {
+    match *form {
+        Cons(_, ref dorsal, _)  => &**dorsal,
+        _                       => form
+    }
+}
+
+///Whether two identifiers were written with the same name.
+fn same_name(a: &SExpr, b: &SExpr) -> bool {
+    match (a, b) {
+        (&Data(ref a), &Data(ref b))    => match (base_name(a), base_name(b)) {
+            (Some(a), Some(b))  => a == b,
+            _                   => false
+        },
+        _   => false
+    }
+}
+
+///Replaces every alias in a datum with the identifier it was written as, for
+///when a template's identifiers are used as data rather than code.
+pub fn strip(datum: &SExpr) -> SExpr {
+    match *datum {
+        Data(Alias(ref renaming))   => Data(renaming.original.clone()),
+        Cons(ref anterior, ref dorsal, pos) => Cons(box strip(&**anterior), box strip(&**dorsal), pos),
+        _   => datum.clone()
+    }
+}
+
+impl Rules {
+    ///Rewrites a use of the macro with the template of the first rule whose
+    ///pattern it matches. The macro keyword itself is not matched.
+    pub fn expand(&self, form: &SExpr) -> Result<SExpr, StrBuf> {
+        for &(ref pattern, ref template) in self.rules.iter() {
+            let mut bindings = HashMap::new();
+            if self.match_pattern(rest(pattern), rest(form), &mut bindings) {
+                let mut renames = HashMap::new();
+                return self.instantiate(template, &bindings, &mut renames, false)
+            }
+        }
+        Err(format!("No syntax rule matches {}", form).to_strbuf())
+    }
+
+    fn is_ellipsis(&self, expr: &SExpr) -> bool {
+        match identifier_key(expr) {
+            Some(key)   => key == self.ellipsis,
+            None        => false
+        }
+    }
+
+    ///Returns the index of the first subpattern followed by an ellipsis.
+    fn ellipsis_index(&self, items: &Vec<SExpr>) -> Option<uint> {
+        for i in range(1, items.len()) {
+            if self.is_ellipsis(items.get(i)) {
+                return Some(i - 1)
+            }
+        }
+        None
+    }
+
+    ///Returns the pattern variables of a pattern.
+    fn pattern_vars(&self, pattern: &SExpr) -> Vec<StrBuf> {
+        match identifier_key(pattern) {
+            Some(key)   => {
+                if key.as_slice() == "_" || key == self.ellipsis || self.literals.contains(&key) {
+                    Vec::new()
+                } else {
+                    vec!(key)
+                }
+            }
+            None        => match *pattern {
+                Cons(ref anterior, ref dorsal, _)   => {
+                    let mut vars = self.pattern_vars(&**anterior);
+                    vars.push_all_move(self.pattern_vars(&**dorsal));
+                    vars
+                }
+                _   => Vec::new()
+            }
+        }
+    }
+
+    ///Matches a form against a pattern, recording what each pattern variable
+    ///matched.
+    fn match_pattern(&self, pattern: &SExpr, form: &SExpr,
+                     bindings: &mut HashMap<StrBuf, Binding>) -> bool {
+        match identifier_key(pattern) {
+            Some(key)   => {
+                if key.as_slice() == "_" {
+                    return true
+                } else if self.literals.contains(&key) {
+                    return same_name(pattern, form)
+                }
+                bindings.insert(key, One(form.clone()));
+                return true
+            }
+            None        => { }
+        }
+
+        match *pattern {
+            Cons(..)    => { }
+            _           => return pattern == form
+        }
+
+        let (items, tail) = elements(pattern);
+        let (forms, form_tail) = elements(form);
+
+        match self.ellipsis_index(&items) {
+            Some(index) => {
+                let after = items.len() - index - 2;
+                if forms.len() < index + after {
+                    return false
+                }
+                let repetitions = forms.len() - index - after;
+
+                for i in range(0, index) {
+                    if !self.match_pattern(items.get(i), forms.get(i), bindings) {
+                        return false
+                    }
+                }
+
+                let repeated = items.get(index);
+                let mut matches = Vec::new();
+                for i in range(index, index + repetitions) {
+                    let mut repetition = HashMap::new();
+                    if !self.match_pattern(repeated, forms.get(i), &mut repetition) {
+                        return false
+                    }
+                    matches.push(repetition);
+                }
+                for var in self.pattern_vars(repeated).move_iter() {
+                    let each = matches.iter().map(|m| m.get(&var).clone()).collect();
+                    bindings.insert(var, Many(each));
+                }
+
+                for i in range(0, after) {
+                    if !self.match_pattern(items.get(index + 2 + i),
+                                           forms.get(index + repetitions + i), bindings) {
+                        return false
+                    }
+                }
+
+                self.match_pattern(&tail, &form_tail, bindings)
+            }
+            None        => {
+                if forms.len() < items.len() {
+                    return false
+                }
+                for i in range(0, items.len()) {
+                    if !self.match_pattern(items.get(i), forms.get(i), bindings) {
+                        return false
+                    }
+                }
+                let leftover = vec_to_improper_list(
+                        Vec::from_slice(forms.slice_from(items.len())), form_tail);
+                self.match_pattern(&tail, &leftover, bindings)
+            }
+        }
+    }
+
+    ///Fills in a template with what the pattern variables matched. Any other
+    ///identifier is replaced by an alias, the same one each time it appears.
+    ///Within `(... template)` ellipses are escaped and stand for themselves.
+    fn instantiate(&self, template: &SExpr, bindings: &HashMap<StrBuf, Binding>,
+                   renames: &mut HashMap<StrBuf, SExpr>, escaped: bool)
+                                                    -> Result<SExpr, StrBuf> {
+        match identifier_key(template) {
+            Some(key)   => return match bindings.find(&key) {
+                Some(&One(ref form))    => Ok(form.clone()),
+                Some(&Many(_))          => Err(format!(
+                        "Pattern variable {} needs an ellipsis in the template", key).to_strbuf()),
+                None                    => Ok(self.rename(template, key, renames))
+            },
+            None        => { }
+        }
+
+        match *template {
+            Cons(..)    => { }
+            _           => return Ok(template.clone())
+        }
+
+        let (items, tail) = elements(template);
+        if !escaped && items.len() == 2 && self.is_ellipsis(items.get(0)) {
+            return self.instantiate(items.get(1), bindings, renames, true)
+        }
+
+        let mut result = Vec::new();
+        let mut i = 0;
+        while i < items.len() {
+            let mut depth = 0;
+            while !escaped && i + depth + 1 < items.len() && self.is_ellipsis(items.get(i + depth + 1)) {
+                depth += 1;
+            }
+            if depth == 0 {
+                result.push(try!(self.instantiate(items.get(i), bindings, renames, escaped)));
+            } else {
+                result.push_all_move(try!(self.repeat(items.get(i), bindings, renames, depth)));
+            }
+            i += depth + 1;
+        }
+
+        let tail = try!(self.instantiate(&tail, bindings, renames, escaped));
+        Ok(vec_to_improper_list(result, tail))
+    }
+
+    ///Fills in a template followed by `depth` ellipses once for each of the
+    ///repetitions its pattern variables matched.
+    fn repeat(&self, template: &SExpr, bindings: &HashMap<StrBuf, Binding>,
+              renames: &mut HashMap<StrBuf, SExpr>, depth: uint) -> Result<Vec<SExpr>, StrBuf> {
+        if depth == 0 {
+            return Ok(vec!(try!(self.instantiate(template, bindings, renames, false))))
+        }
+
+        let mut vars = Vec::new();
+        let mut count = None;
+        for var in template_identifiers(template).move_iter() {
+            match bindings.find(&var) {
+                Some(&Many(ref each))   => {
+                    match count {
+                        Some(n) if n != each.len()  => return Err(format!(
+                                "Pattern variable {} repeats a different number of times",
+                                var).to_strbuf()),
+                        _   => count = Some(each.len())
+                    }
+                    vars.push(var.clone());
+                }
+                _   => { }
+            }
+        }
+
+        let count = match count {
+            Some(count) => count,
+            None        => return Err(format!(
+                    "No pattern variable to repeat in {}", template).to_strbuf())
+        };
+
+        let mut results = Vec::new();
+        for i in range(0, count) {
+            let mut repetition = bindings.clone();
+            for var in vars.iter() {
+                let each = match bindings.find(var) {
+                    Some(&Many(ref each))   => each.get(i).clone(),
+                    _                       => unreachable!()
+                };
+                repetition.insert(var.clone(), each);
+            }
+            results.push_all_move(try!(self.repeat(template, &repetition, renames, depth - 1)));
+        }
+        Ok(results)
+    }
+
+    ///Returns the alias for an identifier the template introduces.
+    fn rename(&self, id: &SExpr, key: StrBuf, renames: &mut HashMap<StrBuf, SExpr>) -> SExpr {
+        match renames.find(&key) {
+            Some(alias) => return alias.clone(),
+            None        => { }
+        }
+
+        let original = match *id {
+            Data(ref original)  => original.clone(),
+            _                   => unreachable!()
+        };
+        //no identifier read from source can contain a space
+        let alias_key = format!("{} {}", base_name(&original).unwrap(),
+                                fresh_count()).to_strbuf();
+
+        let alias = Data(Alias(Rc::new(Renaming {
+            key: alias_key,
+            original: original,
+            env: self.env.clone()
+        })));
+        renames.insert(key, alias.clone());
+        alias
+    }
+}
+
+///Collects the keys of every identifier in a template.
+fn template_identifiers(template: &SExpr) -> Vec<StrBuf> {
+    match identifier_key(template) {
+        Some(key)   => vec!(key),
+        None        => match *template {
+            Cons(ref anterior, ref dorsal, _)   => {
+                let mut keys = template_identifiers(&**anterior);
+                keys.push_all_move(template_identifiers(&**dorsal));
+                keys
+            }
+            _   => Vec::new()
+        }
+    }
+}
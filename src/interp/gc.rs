@@ -0,0 +1,296 @@
+//! Frees the frames which reference counting alone can't.
+//!
+//! A closure keeps the frame it was made in, and a frame keeps whatever its
+//! variables are bound to, so a procedure defined inside another's body is a
+//! cycle of references which never drops to zero. Every frame is registered
+//! as it's made, with the registry of the global environment it's made
+//! under. Between evaluations, the frames in a global environment's registry
+//! which can still be reached from it are marked, and the rest are emptied,
+//! which breaks their cycles so that they can be freed.
+
+use std::mem;
+use std::cmp::max;
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use super::collections::{HashMap, HashSet};
+use super::{SExpr, Cons, Data, Literal, Alias, Env, Environment};
+use super::basictype;
+use super::basictype::{BasicType, Pair, Procedure, Continuation, Composable, Syntax, Macro};
+use machine = super::eval::eval;
+use super::eval::eval::{Frame, Winder, Arguments, Branch, Sequence, CondTest, CaseKey};
+use super::eval::eval::{Receive, Guard, Definition, Assignment, MacroDefinition};
+use super::eval::eval::{Expansion, Reexpand, Conjunction, Disjunction, WindEnter, WindExit};
+use super::eval::eval::{Restore, Rewind, QuasiTail, QuasiJoin, QuasiWrap, Mapping, Prompt};
+
+///How many frames may be registered before the ones already freed are
+///forgotten, at the least.
+static MIN_LIMIT: uint = 1024;
+
+///Every frame made under a global environment which may still be alive.
+///Frames are forgotten once they've been freed, whenever the registry has
+///doubled in size since the last time. Sandboxes made by the code running
+///in a global environment share its registry, since they're only kept alive
+///by what that code holds on to.
+pub struct Registry {
+    frames: Vec<Weak<RefCell<Environment>>>,
+    limit: uint
+}
+
+///Makes a registry with no frames in it, for a new global environment.
+pub fn new_registry() -> Rc<RefCell<Registry>> {
+    Rc::new(RefCell::new(Registry {
+        frames: Vec::new(),
+        limit: MIN_LIMIT
+    }))
+}
+
+///Registers a new frame, so that it can be emptied if it's ever left in a
+///cycle.
+pub fn register(frame: &Env) {
+    let registry = frame.borrow().registry.clone();
+    let mut registry = registry.borrow_mut();
+    if registry.frames.len() >= registry.limit {
+        registry.frames.retain(|frame| frame.upgrade().is_some());
+        registry.limit = max(MIN_LIMIT, registry.frames.len() * 2);
+    }
+    registry.frames.push(frame.downgrade());
+}
+
+///Frees the frames in `global`'s registry which can't be reached from
+///`global` or `value`, but are kept alive by cycles. This must only happen
+///between evaluations, since the frames an evaluation is using might not be
+///reachable from either.
+pub fn collect(global: &Env, value: &SExpr) {
+    let mut marker = Marker { seen: HashSet::new() };
+    marker.env(global);
+    marker.sexpr(value);
+
+    let registry = global.borrow().registry.clone();
+    let garbage = {
+        let mut registry = registry.borrow_mut();
+        let mut live = Vec::new();
+        let mut garbage = Vec::new();
+        for weak in registry.frames.iter() {
+            match weak.upgrade() {
+                Some(frame) => if marker.seen.contains(&address(&*frame)) {
+                    live.push(weak.clone());
+                } else {
+                    garbage.push(frame);
+                },
+                None        => { }
+            }
+        }
+        registry.frames = live;
+        garbage
+    };
+
+    //each frame is emptied before anything in it is dropped, so that none
+    //of them is borrowed while the others are freed
+    for frame in garbage.iter() {
+        let variables = mem::replace(&mut frame.borrow_mut().variables, HashMap::new());
+        let parent = frame.borrow_mut().parent.take();
+        drop(variables);
+        drop(parent);
+    }
+}
+
+fn address<T>(object: &T) -> uint {
+    object as *T as uint
+}
+
+///Follows every reference which can keep a frame alive, remembering the
+///address of each shared object it has been through.
+struct Marker {
+    seen: HashSet<uint>
+}
+
+impl Marker {
+    ///Whether an object is being reached for the first time.
+    fn first_visit<T>(&mut self, object: &T) -> bool {
+        self.seen.insert(address(object))
+    }
+
+    fn env(&mut self, env: &Env) {
+        let mut next = Some(env.clone());
+        loop {
+            let env = match next.take() {
+                Some(env)   => env,
+                None        => return
+            };
+            if !self.first_visit(&*env) {
+                return
+            }
+            let frame = env.borrow();
+            for value in frame.variables.values() {
+                self.sexpr(value);
+            }
+            next = frame.parent.clone();
+        }
+    }
+
+    fn sexpr(&mut self, expr: &SExpr) {
+        match *expr {
+            Cons(ref anterior, ref dorsal, _)   => {
+                self.sexpr(&**anterior);
+                self.sexpr(&**dorsal);
+            }
+            Data(Alias(ref renaming))   => {
+                if self.first_visit(&**renaming) {
+                    self.env(&renaming.env);
+                    self.sexpr(&Data(renaming.original.clone()));
+                }
+            }
+            Data(Literal(ref value))    => self.value(value),
+            _   => { }
+        }
+    }
+
+    fn value(&mut self, value: &BasicType) {
+        match *value {
+            //lists are followed along their cdrs without recursing, since
+            //they may be long
+            Pair(ref pair)  => {
+                let mut next = Some(pair.clone());
+                loop {
+                    let pair = match next.take() {
+                        Some(pair)  => pair,
+                        None        => return
+                    };
+                    if !self.first_visit(&*pair) {
+                        return
+                    }
+                    self.sexpr(&*pair.car.borrow());
+                    let rest = pair.cdr.borrow().clone();
+                    match rest {
+                        Data(Literal(Pair(rest)))   => next = Some(rest),
+                        rest                        => self.sexpr(&rest)
+                    }
+                }
+            }
+            Procedure(ref closure)  => {
+                if self.first_visit(&**closure) {
+                    self.env(&closure.env);
+                    self.sexpr(&closure.body);
+                }
+            }
+            Continuation(ref captured)  => {
+                if self.first_visit(&**captured) {
+                    self.continuation(&captured.cont);
+                    self.winders(&captured.winders);
+                }
+            }
+            Composable(ref subcontinuation) => {
+                if self.first_visit(&**subcontinuation) {
+                    for frame in subcontinuation.frames.iter() {
+                        self.frame(frame);
+                    }
+                }
+            }
+            Syntax(ref rules)   => {
+                if self.first_visit(&**rules) {
+                    self.env(&rules.env);
+                    for &(ref pattern, ref template) in rules.rules.iter() {
+                        self.sexpr(pattern);
+                        self.sexpr(template);
+                    }
+                }
+            }
+            Macro(ref transformer)  => self.sexpr(&**transformer),
+            basictype::Environment(ref env) => self.env(env),
+            _   => { }
+        }
+    }
+
+    fn continuation(&mut self, cont: &Option<Rc<machine::Continuation>>) {
+        let mut next = cont.clone();
+        loop {
+            let cont = match next.take() {
+                Some(cont)  => cont,
+                None        => return
+            };
+            if !self.first_visit(&*cont) {
+                return
+            }
+            self.frame(&cont.frame);
+            next = cont.next.clone();
+        }
+    }
+
+    fn winders(&mut self, winders: &Option<Rc<Winder>>) {
+        let mut next = winders.clone();
+        loop {
+            let winder = match next.take() {
+                Some(winder)    => winder,
+                None            => return
+            };
+            if !self.first_visit(&*winder) {
+                return
+            }
+            self.sexpr(&winder.before);
+            self.sexpr(&winder.after);
+            next = winder.next.clone();
+        }
+    }
+
+    fn frame(&mut self, frame: &Frame) {
+        match *frame {
+            Arguments(ref done, ref rest, ref env, _)   => {
+                for value in done.iter() {
+                    self.sexpr(value);
+                }
+                self.sexpr(rest);
+                self.env(env);
+            }
+            Branch(ref consequent, ref alternative, ref env)    => {
+                self.sexpr(consequent);
+                match *alternative {
+                    Some(ref alternative)   => self.sexpr(alternative),
+                    None                    => { }
+                }
+                self.env(env);
+            }
+            Sequence(ref body, ref env) | CaseKey(ref body, ref env, _) |
+            Guard(ref body, ref env, _) | Conjunction(ref body, ref env) |
+            Disjunction(ref body, ref env) | QuasiTail(ref body, _, ref env, _, _)   => {
+                self.sexpr(body);
+                self.env(env);
+            }
+            CondTest(ref body, ref rest, ref env)   => {
+                self.sexpr(body);
+                self.sexpr(rest);
+                self.env(env);
+            }
+            Definition(_, ref env, _) | MacroDefinition(_, ref env, _) | Expansion(ref env) |
+            Reexpand(ref env)   => {
+                self.env(env);
+            }
+            Assignment(ref id, ref env, _)  => {
+                self.sexpr(&Data(id.clone()));
+                self.env(env);
+            }
+            Receive(ref value) | WindExit(ref value) | Restore(ref value) |
+            QuasiJoin(ref value, _, _)  => self.sexpr(value),
+            WindEnter(ref before, ref thunk, ref after) => {
+                self.sexpr(before);
+                self.sexpr(thunk);
+                self.sexpr(after);
+            }
+            Rewind(ref steps, ref captured, ref value)  => {
+                for &(ref thunk, ref winders) in steps.iter() {
+                    self.sexpr(thunk);
+                    self.winders(winders);
+                }
+                self.value(&Continuation(captured.clone()));
+                self.sexpr(value);
+            }
+            Mapping(ref procedure, ref lists, ref results, _)   => {
+                self.sexpr(procedure);
+                for list in lists.iter() {
+                    self.sexpr(list);
+                }
+                self.sexpr(results);
+            }
+            QuasiWrap(_) | Prompt   => { }
+        }
+    }
+}
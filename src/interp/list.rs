@@ -0,0 +1,226 @@
+//! Lists, made of the pairs built while a program runs.
+
+use super::{SExpr, Cons, Data, Literal, Nil};
+use super::vec_to_data;
+use super::basictype::{Pair, Number};
+use super::number::Integer;
+
+///The names of the compositions of car and cdr, from caar to cddddr. The
+///letters between the c and the r are applied from right to left.
+pub static CXRS: [&'static str, ..28] = [
+    "caar", "cadr", "cdar", "cddr",
+    "caaar", "caadr", "cadar", "caddr", "cdaar", "cdadr", "cddar", "cdddr",
+    "caaaar", "caaadr", "caadar", "caaddr", "cadaar", "cadadr", "caddar", "cadddr",
+    "cdaaar", "cdaadr", "cdadar", "cdaddr", "cddaar", "cddadr", "cdddar", "cddddr"
+];
+
+fn not_a_pair<T>(name: &str, x: &SExpr) -> Result<T, StrBuf> {
+    Err(format!("{}: expected a pair, got {}", name, x).to_strbuf())
+}
+
+///The first half of a pair. `name` is the operator asking, for the error.
+pub fn car(name: &str, x: &SExpr) -> Result<SExpr, StrBuf> {
+    match *x {
+        Data(Literal(Pair(ref pair)))   => Ok(pair.car.borrow().clone()),
+        Cons(ref anterior, _, _)        => Ok(*anterior.clone()),
+        _                               => not_a_pair(name, x)
+    }
+}
+
+///The second half of a pair.
+pub fn cdr(name: &str, x: &SExpr) -> Result<SExpr, StrBuf> {
+    match *x {
+        Data(Literal(Pair(ref pair)))   => Ok(pair.cdr.borrow().clone()),
+        Cons(_, ref dorsal, _)          => Ok(*dorsal.clone()),
+        _                               => not_a_pair(name, x)
+    }
+}
+
+///Applies one of the compositions of car and cdr in CXRS.
+pub fn cxr(index: uint, x: &SExpr) -> Result<SExpr, StrBuf> {
+    let name = CXRS[index];
+    let mut value = x.clone();
+    for c in name.slice(1, name.len() - 1).chars().rev() {
+        value = if c == 'a' {
+            try!(car(name, &value))
+        } else {
+            try!(cdr(name, &value))
+        };
+    }
+    Ok(value)
+}
+
+///Changes the first half of a pair. Code read from source can't be changed.
+pub fn set_car(x: &SExpr, value: SExpr) -> Result<(), StrBuf> {
+    match *x {
+        Data(Literal(Pair(ref pair)))   => {
+            *pair.car.borrow_mut() = value;
+            Ok(())
+        }
+        _   => not_a_pair("set-car!", x)
+    }
+}
+
+pub fn set_cdr(x: &SExpr, value: SExpr) -> Result<(), StrBuf> {
+    match *x {
+        Data(Literal(Pair(ref pair)))   => {
+            *pair.cdr.borrow_mut() = value;
+            Ok(())
+        }
+        _   => not_a_pair("set-cdr!", x)
+    }
+}
+
+pub fn is_pair(x: &SExpr) -> bool {
+    match *x {
+        Data(Literal(Pair(_))) | Cons(_, _, _)  => true,
+        _                                       => false
+    }
+}
+
+///The length of a proper list, or None if the list is improper or circular.
+///A second cursor moves at twice the speed, and catches up with the first
+///only if the list goes round in a circle.
+fn proper_length(x: &SExpr) -> Option<uint> {
+    let mut slow = x.clone();
+    let mut fast = x.clone();
+    let mut length = 0;
+    loop {
+        for _ in range(0, 2) {
+            if fast == Nil {
+                return Some(length)
+            }
+            fast = match cdr("", &fast) {
+                Ok(rest)    => rest,
+                Err(_)      => return None
+            };
+            length += 1;
+        }
+        slow = cdr("", &slow).unwrap();
+        if is_pair(&fast) && fast == slow {
+            return None
+        }
+    }
+}
+
+pub fn is_list(x: &SExpr) -> bool {
+    proper_length(x).is_some()
+}
+
+pub fn length(x: &SExpr) -> Result<SExpr, StrBuf> {
+    match proper_length(x) {
+        Some(length)    => Ok(Data(Literal(Number(Integer(length as int))))),
+        None            => Err(format!("length: expected a proper list, got {}", x).to_strbuf())
+    }
+}
+
+///The elements of a proper list.
+fn elements(name: &str, x: &SExpr) -> Result<Vec<SExpr>, StrBuf> {
+    if !is_list(x) {
+        return Err(format!("{}: expected a proper list, got {}", name, x).to_strbuf())
+    }
+    let mut terms = Vec::new();
+    let mut rest = x.clone();
+    while rest != Nil {
+        terms.push(try!(car(name, &rest)));
+        rest = try!(cdr(name, &rest));
+    }
+    Ok(terms)
+}
+
+///Joins lists together. Every list but the last is copied, and the last is
+///shared with the result, so it may be anything at all.
+pub fn append(lists: &Vec<SExpr>) -> Result<SExpr, StrBuf> {
+    let mut terms = Vec::new();
+    let tail = match lists.last() {
+        Some(tail)  => tail.clone(),
+        None        => return Ok(Nil)
+    };
+    for list in lists.slice_to(lists.len() - 1).iter() {
+        terms.push_all_move(try!(elements("append", list)));
+    }
+    Ok(vec_to_data(terms, tail))
+}
+
+pub fn reverse(x: &SExpr) -> Result<SExpr, StrBuf> {
+    let mut terms = try!(elements("reverse", x));
+    terms.reverse();
+    Ok(vec_to_data(terms, Nil))
+}
+
+///Whether two values are the same, as eq? and eqv? decide. Pairs and
+///procedures are the same only as themselves, while numbers, characters,
+///strings and symbols are the same if they're written the same way.
+pub fn is_eqv(a: &SExpr, b: &SExpr) -> bool {
+    a == b
+}
+
+///Whether two values print the same, comparing lists element by element.
+///The cdrs are followed in a loop, so that long lists don't exhaust the
+///stack, and only the cars are compared recursively. Two circular lists are
+///equal once both come back round to cells they were already compared at
+///together, which is found the way proper_length finds a cycle.
+pub fn is_equal(a: &SExpr, b: &SExpr) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    let (mut slow_a, mut slow_b) = (a.clone(), b.clone());
+    let mut steps = 0u;
+    loop {
+        if !is_pair(&a) || !is_pair(&b) {
+            return is_eqv(&a, &b)
+        }
+        if !is_equal(&car("", &a).unwrap(), &car("", &b).unwrap()) {
+            return false
+        }
+        a = cdr("", &a).unwrap();
+        b = cdr("", &b).unwrap();
+        steps += 1;
+        if steps % 2 == 0 {
+            slow_a = cdr("", &slow_a).unwrap();
+            slow_b = cdr("", &slow_b).unwrap();
+        }
+        if is_pair(&a) && a == slow_a && b == slow_b {
+            return true
+        }
+    }
+}
+
+///Reads the index given to list-tail or list-ref.
+fn index(name: &str, k: &SExpr) -> Result<uint, StrBuf> {
+    match *k {
+        Data(Literal(Number(Integer(n)))) if n >= 0 => Ok(n as uint),
+        _   => Err(format!("{}: expected a nonnegative exact integer, got {}", name, k).to_strbuf())
+    }
+}
+
+///What's left of a list after its first k elements.
+pub fn list_tail(name: &str, x: &SExpr, k: &SExpr) -> Result<SExpr, StrBuf> {
+    let mut rest = x.clone();
+    for _ in range(0, try!(index(name, k))) {
+        rest = match cdr(name, &rest) {
+            Ok(rest)    => rest,
+            Err(_)      => return Err(format!("{}: index {} is past the end of {}",
+                                              name, k, x).to_strbuf())
+        };
+    }
+    Ok(rest)
+}
+
+pub fn list_ref(x: &SExpr, k: &SExpr) -> Result<SExpr, StrBuf> {
+    let rest = try!(list_tail("list-ref", x, k));
+    match car("list-ref", &rest) {
+        Ok(element) => Ok(element),
+        Err(_)      => Err(format!("list-ref: index {} is past the end of {}", k, x).to_strbuf())
+    }
+}
+
+///Copies the pairs of a list, keeping whatever ends it. Anything other than
+///a pair is returned as it is.
+pub fn list_copy(x: &SExpr) -> Result<SExpr, StrBuf> {
+    let mut terms = Vec::new();
+    let mut rest = x.clone();
+    while is_pair(&rest) {
+        terms.push(try!(car("list-copy", &rest)));
+        rest = try!(cdr("list-copy", &rest));
+    }
+    Ok(vec_to_data(terms, rest))
+}
@@ -2,99 +2,541 @@
 
 extern crate collections;
 
-use self::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc;
+use std::rc::Rc;
+use std::cell::RefCell;
+use self::collections::{HashMap, HashSet};
 use self::basictype::BasicType;
 use self::operator::OperatorType;
 use self::read::translate::parse;
-use self::read::tokenize::TokenStream;
+use self::read::tokenize::{TokenStream, TokenIterator, Position};
 use self::eval::eval;
 
 pub mod basictype;
+pub mod number;
+pub mod list;
 pub mod operator;
 pub mod read;
 pub mod eval;
+pub mod gc;
 
-///Boxing used to dynamically allocate memory to allow for the recursive data 
+#[cfg(test)]
+mod tests;
+
+///The procedures defined in every global environment, written in risp.
+static PRELUDE: &'static str = include_str!("prelude.scm");
+
+///Boxing used to dynamically allocate memory to allow for the recursive data
 ///structure. Data can be anything - an operator, a number, a string,
-///anything which has a type.
-#[deriving(Show)]
+///anything which has a type. Cells read from source remember the position
+///of their opening paren so errors can point back at them.
 #[deriving(Clone)]
-#[deriving(Eq)]
 pub enum SExpr {
     Data(DataType),
-    Cons(Box<SExpr>, Box<SExpr>),
+    Cons(Box<SExpr>, Box<SExpr>, Option<Position>),
     Nil,
 }
 
 ///DataTypes are either operators or literals. I think this is kind of shaky
 ///right now because I don't know how functions and variables are going to
-///work yet.
-#[deriving(Show)]
+///work yet. Aliases are identifiers put in place by macro expansions.
 #[deriving(Clone)]
 #[deriving(Eq)]
 pub enum DataType {
     Operator(OperatorType),
     Literal(BasicType),
-    Variable(StrBuf)
+    Variable(StrBuf),
+    Alias(Rc<Renaming>)
+}
+
+///Positions are bookkeeping, so two cells are equal whenever their contents
+///are.
+impl Eq for SExpr {
+    fn eq(&self, other: &SExpr) -> bool {
+        match (self, other) {
+            (&Data(ref a), &Data(ref b))                    => a == b,
+            (&Cons(ref a, ref b, _), &Cons(ref c, ref d, _))  => a == c && b == d,
+            (&Nil, &Nil)                                    => true,
+            _                                               => false
+        }
+    }
+}
+
+///Prints an SExpr the way it would be written in source.
+impl fmt::Show for SExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Data(ref data)  => write!(f, "{}", data),
+            Nil             => write!(f, "()"),
+            Cons(ref anterior, ref dorsal, _)   => {
+                try!(write!(f, "({}", anterior));
+                let mut rest = &**dorsal;
+                loop {
+                    match *rest {
+                        Cons(ref anterior, ref dorsal, _)   => {
+                            try!(write!(f, " {}", anterior));
+                            rest = &**dorsal;
+                        }
+                        Nil     => break,
+                        Data(ref data)  => {
+                            try!(write!(f, " . {}", data));
+                            break
+                        }
+                    }
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Show for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operator(op)        => write!(f, "{}", operator::to_str(op)),
+            Literal(ref ty)     => write!(f, "{}", ty),
+            Variable(ref name)  => write!(f, "{}", name),
+            Alias(ref renaming) => write!(f, "{}", renaming.original)
+        }
+    }
 }
 
-///Returns the first atom found in a cell. E.g,
-///car(Cons(box Data(Operator(Add))), box Data(Literal(Number(5))));
-///returns +, where car(Cons(box Cons(box Literal(Number 7)), box Nil), box Nil)
-///returns Cons(box Literal(Number 7), box Nil)
-pub fn car(sexpr: &SExpr) -> Result<SExpr, bool> {
-    match sexpr {
-        &Data(ref anterior)     => Ok(Data(anterior.clone())),
-        &Cons(ref anterior, _)  => Ok(*anterior.clone()),
-        &Nil        => Err(false) //need to figure out how to represent nil
+///Makes a new cell which has no source position.
+pub fn cons(anterior: SExpr, dorsal: SExpr) -> SExpr {
+    Cons(box anterior, box dorsal, None)
+}
+
+///Collects the elements of a proper list into a vector, or Errs if the
+///list is improper. The list may be code or data.
+pub fn list_to_vec(list: &SExpr) -> Result<Vec<SExpr>, StrBuf> {
+    let mut terms = Vec::new();
+    let mut rest = list;
+    loop {
+        match *rest {
+            Cons(ref anterior, ref dorsal, _)   => {
+                terms.push(*anterior.clone());
+                rest = &**dorsal;
+            }
+            Nil     => return Ok(terms),
+            Data(Literal(basictype::Pair(_)))   => {
+                let mut rest = rest.clone();
+                loop {
+                    rest = match rest {
+                        Data(Literal(basictype::Pair(pair)))    => {
+                            terms.push(pair.car.borrow().clone());
+                            pair.cdr.borrow().clone()
+                        }
+                        Nil     => return Ok(terms),
+                        _       => return Err("Expected a proper list!".to_strbuf())
+                    }
+                }
+            }
+            Data(_) => return Err("Expected a proper list!".to_strbuf())
+        }
+    }
+}
+
+///Builds a proper list from a vector of SExprs.
+pub fn vec_to_list(terms: Vec<SExpr>) -> SExpr {
+    vec_to_improper_list(terms, Nil)
+}
+
+///Builds a list from a vector of SExprs whose final cell's dorsal region is
+///`tail` rather than Nil.
+pub fn vec_to_improper_list(terms: Vec<SExpr>, tail: SExpr) -> SExpr {
+    let mut list = tail;
+    for term in terms.move_iter().rev() {
+        list = cons(term, list);
+    }
+    list
+}
+
+///A pair made while the program runs, as opposed to the cells of code read
+///from source. Either half can be changed by set-car! or set-cdr!, and the
+///change is seen by everything which shares the pair.
+pub struct MutablePair {
+    pub car: RefCell<SExpr>,
+    pub cdr: RefCell<SExpr>
+}
+
+///A long list would overflow the stack if each pair dropped the next in
+///turn, so the cdrs are unlinked here for as long as nothing else shares
+///them.
+impl Drop for MutablePair {
+    fn drop(&mut self) {
+        let mut rest = mem::replace(&mut *self.cdr.borrow_mut(), Nil);
+        loop {
+            rest = match rest {
+                Data(Literal(basictype::Pair(pair)))    => match rc::try_unwrap(pair) {
+                    Ok(pair)    => {
+                        let rest = mem::replace(&mut *pair.cdr.borrow_mut(), Nil);
+                        rest
+                    }
+                    Err(_)      => return
+                },
+                _   => return
+            };
+        }
     }
 }
 
-///Returns the dorsal region of a cell, or Errs if the SExpr is an atom.
-pub fn cdr(sexpr: &SExpr) -> Result<SExpr, bool> {
-    match sexpr {
-        &Cons(_, ref dorsal)    => Ok(*dorsal.clone()),
-        &Nil | &Data(_)         => Err(false)
+///Pairs are only ever equal to themselves.
+impl Eq for MutablePair {
+    fn eq(&self, other: &MutablePair) -> bool {
+        (self as *MutablePair) == (other as *MutablePair)
     }
 }
 
+///Makes a new pair of data.
+pub fn make_pair(car: SExpr, cdr: SExpr) -> SExpr {
+    Data(Literal(basictype::Pair(Rc::new(MutablePair {
+        car: RefCell::new(car),
+        cdr: RefCell::new(cdr)
+    }))))
+}
+
+///Builds a list of data from a vector of SExprs, ending in `tail`.
+pub fn vec_to_data(terms: Vec<SExpr>, tail: SExpr) -> SExpr {
+    let mut list = tail;
+    for term in terms.move_iter().rev() {
+        list = make_pair(term, list);
+    }
+    list
+}
+
+///Turns code into data made of fresh pairs, as quote does.
+pub fn to_data(expr: &SExpr) -> SExpr {
+    match *expr {
+        Cons(ref anterior, ref dorsal, _)   => make_pair(to_data(&**anterior), to_data(&**dorsal)),
+        _                                   => expr.clone()
+    }
+}
+
+///Turns data into code which can be evaluated, such as the expansion a
+///macro transformer returns.
+pub fn to_syntax(expr: &SExpr) -> SExpr {
+    match *expr {
+        Data(Literal(basictype::Pair(ref pair)))    => {
+            cons(to_syntax(&*pair.car.borrow()), to_syntax(&*pair.cdr.borrow()))
+        }
+        Cons(ref anterior, ref dorsal, pos) => {
+            Cons(box to_syntax(&**anterior), box to_syntax(&**dorsal), pos)
+        }
+        _   => expr.clone()
+    }
+}
+
+///Frames are shared between the procedures which close over them and the
+///applications running in them, so they're reference counted.
+pub type Env = Rc<RefCell<Environment>>;
+
 ///A representation of a frame as a HashMap of SExprs which can either be
-///data in general or SExprs.
+///data in general or SExprs. Variables defined inside a body are declared
+///in its frame before the body runs, and can't be used until their
+///definitions have been evaluated.
 pub struct Environment {
     pub variables: HashMap<StrBuf, SExpr>,
-    pub parent: Option<Box<Environment>>
+    pub declared: HashSet<StrBuf>,
+    pub parent: Option<Env>,
+    pub registry: Rc<RefCell<gc::Registry>>
+}
+
+impl Environment {
+    ///Makes the outermost frame, which has no parent, with the built in
+    ///procedures and the prelude defined in it.
+    pub fn new_global() -> Env {
+        Environment::new_standard(&gc::new_registry())
+    }
+
+    ///Makes an outermost frame with nothing defined in it, so that only the
+    ///special forms can be used.
+    pub fn new_null() -> Env {
+        Environment::new_empty(&gc::new_registry())
+    }
+
+    ///Makes an outermost frame like new_global's, whose frames are kept in
+    ///an existing registry.
+    pub fn new_standard(registry: &Rc<RefCell<gc::Registry>>) -> Env {
+        let global = Environment::new_empty(registry);
+        bind_procedures(&global);
+        load_prelude(&global);
+        global
+    }
+
+    ///Makes an outermost frame like new_null's, whose frames are kept in an
+    ///existing registry.
+    pub fn new_empty(registry: &Rc<RefCell<gc::Registry>>) -> Env {
+        let frame = Rc::new(RefCell::new(Environment {
+            variables: HashMap::new(),
+            declared: HashSet::new(),
+            parent: None,
+            registry: registry.clone()
+        }));
+        gc::register(&frame);
+        frame
+    }
+
+    ///Makes an empty frame enclosed by `parent`.
+    pub fn new_frame(parent: &Env) -> Env {
+        let frame = Rc::new(RefCell::new(Environment {
+            variables: HashMap::new(),
+            declared: HashSet::new(),
+            parent: Some(parent.clone()),
+            registry: parent.borrow().registry.clone()
+        }));
+        gc::register(&frame);
+        frame
+    }
+}
+
+///Environments are only ever equal to themselves.
+impl Eq for Environment {
+    fn eq(&self, other: &Environment) -> bool {
+        (self as *Environment) == (other as *Environment)
+    }
+}
+
+///Binds each built in procedure in `env` under its name.
+fn bind_procedures(env: &Env) {
+    let mut frame = env.borrow_mut();
+    let mut bind = |name: &str, op: OperatorType| {
+        frame.variables.insert(name.to_strbuf(), Data(Literal(basictype::Primitive(op))));
+    };
+    for &op in operator::PROCEDURES.iter() {
+        bind(operator::to_str(op), op);
+    }
+    for (index, &name) in list::CXRS.iter().enumerate() {
+        bind(name, operator::Cxr(index));
+    }
+    bind("call/cc", operator::CallCC);
+}
+
+///Evaluates the forms of the prelude in `env`. They're stripped of their
+///positions, so that an error in a prelude procedure is placed at the form
+///which called it rather than at some line of the prelude.
+fn load_prelude(env: &Env) {
+    let mut tokens = TokenStream::new(PRELUDE.to_strbuf());
+    loop {
+        let expr = match parse(&mut tokens) {
+            Ok(Some(expr))  => to_syntax(&to_data(&expr)),
+            Ok(None)        => return,
+            Err(err)        => fail!("The prelude is unreadable: {}", err.render(PRELUDE))
+        };
+        match eval(&expr, env) {
+            Ok(_)       => { }
+            Err(err)    => fail!("The prelude failed to load: {}", err.msg)
+        }
+    }
 }
 
 ///Returns the value of a variable, whether it's a number, string or a procedure,
 ///or returns an Error if no such variable is found. It recurses through
 ///each parent environment until it reaches the global one.
-pub fn lookup(var: &str, env: &Environment) -> Result<SExpr, StrBuf> {
-    match env.variables.find(&var.to_strbuf()) {
+pub fn lookup(var: &str, env: &Env) -> Result<SExpr, StrBuf> {
+    let frame = env.borrow();
+    let key = var.to_strbuf();
+    match frame.variables.find(&key) {
         Some(val)   => Ok(val.clone()),
-        None        => match env.parent {
-            Some(ref frame) => lookup(var, *frame),
-            None            => Err("Unbound variable: ".to_strbuf().append(var))
+        None if frame.declared.contains(&key)   => {
+            Err(format!("{} is used before its definition", var).to_strbuf())
+        }
+        None        => match frame.parent {
+            Some(ref parent)    => lookup(var, parent),
+            None                => Err("Unbound variable: ".to_strbuf().append(var))
         }
     }
 }
 
-pub fn interp(sexpr: StrBuf, mut global_env: &mut Environment) -> StrBuf {
+///Changes the value of a variable in the nearest frame which binds it, or
+///returns an Error if none does. Unlike a definition, it never makes a new
+///binding.
+pub fn set_variable(var: &str, value: SExpr, env: &Env) -> Result<(), StrBuf> {
+    let key = var.to_strbuf();
+    let mut frame = env.borrow_mut();
+    if frame.variables.contains_key(&key) || frame.declared.contains(&key) {
+        frame.variables.insert(key, value);
+        return Ok(())
+    }
+    match frame.parent {
+        Some(ref parent)    => set_variable(var, value, parent),
+        None                => Err("Cannot set! an unbound variable: ".to_strbuf().append(var))
+    }
+}
 
-    let mut tokens = TokenStream{
-        string_slice: sexpr,
-        string_index: 0
-    };
+///An identifier inserted by a macro expansion in place of `original`. It is
+///bound under a key no source identifier can have, so bindings made by the
+///expansion can't capture the user's identifiers or be captured by them.
+///When nothing in the expansion binds it, it means whatever `original`
+///meant where the macro was defined.
+pub struct Renaming {
+    pub key: StrBuf,
+    pub original: DataType,
+    pub env: Env
+}
 
-    let sexpr = match parse(&mut tokens, global_env) {
-        Ok(good)    => good,
-        Err(msg)    => return msg
-    };
+///Renamings are only ever equal to themselves.
+impl Eq for Renaming {
+    fn eq(&self, other: &Renaming) -> bool {
+        (self as *Renaming) == (other as *Renaming)
+    }
+}
 
-    let result = match eval(&sexpr, global_env) {
-        Ok(good)    => good,
-        Err(msg)    => return msg
-    };
+///Returns the name an identifier is bound under, or None if the SExpr isn't
+///an identifier.
+pub fn identifier_key(expr: &SExpr) -> Option<StrBuf> {
+    match *expr {
+        Data(Variable(ref name))    => Some(name.clone()),
+        Data(Alias(ref renaming))   => Some(renaming.key.clone()),
+        _                           => None
+    }
+}
+
+///Returns the name an identifier was written as in source, looking through
+///any renamings.
+pub fn base_name(id: &DataType) -> Option<StrBuf> {
+    match *id {
+        Variable(ref name)  => Some(name.clone()),
+        Alias(ref renaming) => base_name(&renaming.original),
+        _                   => None
+    }
+}
 
-    result.to_str().to_strbuf()
+///Looks up the value of an identifier. An alias which nothing in its
+///expansion bound is looked up as the identifier it replaced, in the
+///environment its macro was defined in.
+pub fn resolve(id: &DataType, env: &Env) -> Result<SExpr, StrBuf> {
+    match *id {
+        Variable(ref name)  => lookup(name.as_slice(), env),
+        Alias(ref renaming) => match lookup(renaming.key.as_slice(), env) {
+            Ok(value)   => Ok(value),
+            Err(_)      => resolve(&renaming.original, &renaming.env)
+        },
+        _   => Err(format!("Not an identifier: {}", id).to_strbuf())
+    }
+}
+
+///Changes the value of an identifier, looking through renamings the same
+///way resolve does.
+pub fn assign(id: &DataType, value: SExpr, env: &Env) -> Result<(), StrBuf> {
+    match *id {
+        Variable(ref name)  => set_variable(name.as_slice(), value, env),
+        Alias(ref renaming) => match set_variable(renaming.key.as_slice(), value.clone(), env) {
+            Ok(())  => Ok(()),
+            Err(_)  => assign(&renaming.original, value, &renaming.env)
+        },
+        _   => Err(format!("Not an identifier: {}", id).to_strbuf())
+    }
+}
 
+///A procedure made by lambda. It keeps the environment it was made in, and
+///each application of it gets a new frame enclosed by that environment. The
+///body is kept as the list of forms it was written as.
+pub struct Closure {
+    pub params: Vec<StrBuf>,
+    pub rest: Option<StrBuf>,
+    pub body: SExpr,
+    pub env: Env
+}
+
+///Procedures are only ever equal to themselves.
+impl Eq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        (self as *Closure) == (other as *Closure)
+    }
+}
+
+///An error raised while reading or evaluating, along with the position in
+///the source of the form responsible for it, if that is known.
+#[deriving(Show, Clone)]
+pub struct RispError {
+    pub msg: StrBuf,
+    pub pos: Option<Position>
+}
+
+impl RispError {
+    pub fn new(msg: StrBuf, pos: Option<Position>) -> RispError {
+        RispError {
+            msg: msg,
+            pos: pos
+        }
+    }
+
+    ///Places an error at `pos`, unless a more specific position was already
+    ///found for it.
+    pub fn at(self, pos: Option<Position>) -> RispError {
+        match self.pos {
+            Some(_) => self,
+            None    => RispError::new(self.msg, pos)
+        }
+    }
+
+    ///Forgets the position of an error which was found in some other text
+    ///than `source`, such as the body of a procedure read from an earlier
+    ///input, since it can't be shown against this one.
+    pub fn within(self, source: uint) -> RispError {
+        let foreign = match self.pos {
+            Some(ref pos)   => pos.source != source,
+            None            => false
+        };
+        if foreign {
+            RispError::new(self.msg, None)
+        } else {
+            self
+        }
+    }
+
+    ///Formats the message, followed by the offending line of the source and
+    ///a caret pointing at the column where the error was found. The error
+    ///must be `within` that source.
+    pub fn render(&self, source: &str) -> StrBuf {
+        let pos = match self.pos {
+            Some(ref pos)   => pos,
+            None            => return self.msg.clone()
+        };
+
+        let line = source.lines().nth(pos.line - 1).unwrap_or("");
+        let mut msg = format!("line {}, column {}: {}\n{}\n",
+                              pos.line, pos.column, self.msg, line).to_strbuf();
+        for _ in range(1, pos.column) {
+            msg.push_char(' ');
+        }
+        msg.push_char('^');
+        msg
+    }
+}
+
+///Reads every form in the input and evaluates them in order, returning the
+///printed value of the last one.
+pub fn interp(sexpr: StrBuf, global_env: &Env) -> StrBuf {
+
+    let mut tokens = TokenStream::new(sexpr.clone());
+    let mut result = None;
+
+    loop {
+        let start = tokens.peek().map(|token| token.pos);
+
+        let expr = match parse(&mut tokens) {
+            Ok(Some(good))  => good,
+            Ok(None)        => break,
+            Err(err)        => return err.render(sexpr.as_slice())
+        };
+
+        let value = match eval(&expr, global_env) {
+            Ok(good)    => good,
+            Err(err)    => {
+                return err.within(tokens.source).at(start).render(sexpr.as_slice())
+            }
+        };
+        //nothing is being evaluated now, so whatever the value and the
+        //global environment don't lead to can be freed
+        gc::collect(global_env, &value);
+        result = Some(value);
+    }
+
+    match result {
+        Some(value) => value.to_str().to_strbuf(),
+        None        => StrBuf::new()
+    }
 }
@@ -1,15 +1,1097 @@
-//! Numbers
+//! Numbers, and arithmetic across the numeric tower. An operation on two
+//! numbers of different kinds first converts the one lower down the tower to
+//! the kind of the other: integers, then bignums, then exact rationals, then
+//! floating point reals, then complex numbers. Integer arithmetic which
+//! would overflow an int is done with bignums instead, and bignums which fit
+//! in an int again become ints.
 
-pub extern crate num;
+extern crate num;
 
-use self::num::{complex, rational, bigint};
+use std::fmt;
+use std::f64;
+use std::cmp::{max, Ordering, Less, Equal, Greater};
+use std::num::{Zero, One, Float, Signed, ToPrimitive, CheckedAdd, CheckedSub, CheckedMul};
+use std::num::{pow, from_str_radix};
+use self::num::bigint::{BigInt, BigUint, ToBigInt, ToBigUint};
+use self::num::rational::{Ratio, BigRational};
+use self::num::complex::{Complex64};
 
+#[deriving(Clone, Eq)]
 pub enum NumericType {
-    Complex(complex::Complex),
-    BigRational(rational::BigRational),
-    Rational(rational::Rational),
-    BigInteger(bigint::BigInt),
-    Floating(f64),
     Integer(int),
-    UInteger(uint)
+    BigInteger(BigInt),
+    Rational(BigRational),
+    Floating(f64),
+    Complex(Complex64)
+}
+
+impl NumericType {
+    ///How far up the tower a number is.
+    fn rank(&self) -> uint {
+        match *self {
+            Integer(_)      => 0,
+            BigInteger(_)   => 1,
+            Rational(_)     => 2,
+            Floating(_)     => 3,
+            Complex(_)      => 4
+        }
+    }
+
+    ///Converts a number to the kind at `rank`, which must be no lower down
+    ///the tower than the number already is.
+    fn promote(&self, rank: uint) -> NumericType {
+        match rank {
+            0   => self.clone(),
+            1   => BigInteger(self.to_big()),
+            2   => Rational(self.to_rational()),
+            3   => Floating(self.to_float()),
+            _   => Complex(self.to_complex())
+        }
+    }
+
+    fn to_big(&self) -> BigInt {
+        match *self {
+            Integer(x)          => x.to_bigint().unwrap(),
+            BigInteger(ref x)   => x.clone(),
+            _                   => unreachable!()
+        }
+    }
+
+    fn to_rational(&self) -> BigRational {
+        match *self {
+            Rational(ref x) => x.clone(),
+            _               => Ratio::from_integer(self.to_big())
+        }
+    }
+
+    fn to_float(&self) -> f64 {
+        match *self {
+            Integer(x)          => x as f64,
+            BigInteger(ref x)   => big_to_float(x),
+            Rational(ref x)     => big_to_float(x.numer()) / big_to_float(x.denom()),
+            Floating(x)         => x,
+            Complex(_)          => unreachable!()
+        }
+    }
+
+    fn to_complex(&self) -> Complex64 {
+        match *self {
+            Complex(x)  => x,
+            _           => Complex64::new(self.to_float(), 0.0)
+        }
+    }
+
+    ///Integers, bignums and rationals are exact; everything else is not.
+    pub fn is_exact(&self) -> bool {
+        self.rank() < 3
+    }
+
+    ///Whether a number has no imaginary part.
+    pub fn is_real(&self) -> bool {
+        match *self {
+            Complex(x)  => x.im == 0.0,
+            _           => true
+        }
+    }
+
+    ///Whether a number is a real with no fractional part. Infinities and
+    ///NaN aren't integers.
+    pub fn is_integer(&self) -> bool {
+        match *self {
+            Integer(_) | BigInteger(_)  => true,
+            Rational(_)                 => false,
+            Floating(x)                 => x.fract() == 0.0 && !x.is_infinite(),
+            Complex(x)                  => x.im == 0.0 && Floating(x.re).is_integer()
+        }
+    }
+
+    ///Whether a number is a real which a ratio of integers can represent,
+    ///which every finite real can.
+    pub fn is_rational(&self) -> bool {
+        match *self {
+            Floating(x) => !x.is_nan() && !x.is_infinite(),
+            Complex(x)  => x.im == 0.0 && Floating(x.re).is_rational(),
+            _           => true
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match *self {
+            Integer(x)          => x == 0,
+            BigInteger(ref x)   => x.is_zero(),
+            Rational(ref x)     => x.is_zero(),
+            Floating(x)         => x == 0.0,
+            Complex(x)          => x.re == 0.0 && x.im == 0.0
+        }
+    }
+}
+
+///Prints numbers the way they would be written in source.
+impl fmt::Show for NumericType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Integer(x)          => write!(f, "{}", x),
+            BigInteger(ref x)   => write!(f, "{}", x),
+            Rational(ref x)     => write!(f, "{}/{}", x.numer(), x.denom()),
+            Floating(x)         => show_float(f, x),
+            Complex(x)          => {
+                try!(show_float(f, x.re));
+                //negative (even negative zero), infinite and NaN imaginary
+                //parts bring their own sign
+                if !x.im.is_negative() && !x.im.is_infinite() && !x.im.is_nan() {
+                    try!(write!(f, "+"));
+                }
+                try!(show_float(f, x.im));
+                write!(f, "i")
+            }
+        }
+    }
+}
+
+///Writes a float so that it reads back the same, with infinities and NaN
+///spelled the way the reader expects them.
+fn show_float(f: &mut fmt::Formatter, x: f64) -> fmt::Result {
+    if x.is_nan() {
+        write!(f, "{}", "+nan.0")
+    } else if x.is_infinite() && x > 0.0 {
+        write!(f, "{}", "+inf.0")
+    } else if x.is_infinite() {
+        write!(f, "{}", "-inf.0")
+    } else {
+        write!(f, "{}", shortest(x))
+    }
+}
+
+///Writes a finite float with the fewest significant digits which read back
+///as the same float. It's written with an exponent only when it's very large
+///or very small, and otherwise with a point even if nothing follows it, so
+///that it reads back as a float at all.
+fn shortest(x: f64) -> StrBuf {
+    if x == 0.0 {
+        return if x.is_negative() { "-0.0" } else { "0.0" }.to_strbuf()
+    }
+
+    //seventeen significant digits are always enough to tell doubles apart
+    let mut repr = f64::to_str_exp_exact(x, 16).to_strbuf();
+    for precision in range(0u, 16) {
+        let candidate = f64::to_str_exp_exact(x, precision).to_strbuf();
+        if from_str::<f64>(candidate.as_slice()) == Some(x) {
+            repr = candidate;
+            break
+        }
+    }
+
+    //the significant digits, the first of which is in the place of
+    //10^exponent
+    let repr = repr.as_slice().trim_left_chars('-');
+    let (mantissa, exponent) = match repr.find('e') {
+        Some(idx)   => (repr.slice_to(idx), from_str::<int>(repr.slice_from(idx + 1)).unwrap()),
+        None        => (repr, 0)
+    };
+    let mut digits = StrBuf::new();
+    for c in mantissa.chars().filter(|&c| c != '.') {
+        digits.push_char(c);
+    }
+    let digits = digits.as_slice().trim_right_chars('0');
+
+    let mut scientific = StrBuf::from_str(digits.slice_to(1));
+    if digits.len() > 1 {
+        scientific.push_char('.');
+        scientific.push_str(digits.slice_from(1));
+    }
+    scientific.push_str(format!("e{}", exponent).as_slice());
+
+    let mut plain = StrBuf::new();
+    if exponent < 0 {
+        plain.push_str("0.");
+        for _ in range(1, -exponent) {
+            plain.push_char('0');
+        }
+        plain.push_str(digits);
+    } else {
+        let point = exponent as uint + 1;
+        if digits.len() > point {
+            plain.push_str(digits.slice_to(point));
+            plain.push_char('.');
+            plain.push_str(digits.slice_from(point));
+        } else {
+            plain.push_str(digits);
+            for _ in range(digits.len(), point) {
+                plain.push_char('0');
+            }
+            plain.push_str(".0");
+        }
+    }
+
+    let mut written = if x.is_negative() { "-".to_strbuf() } else { StrBuf::new() };
+    written.push_str(if exponent < -6 || exponent >= 21 {
+        scientific.as_slice()
+    } else {
+        plain.as_slice()
+    });
+    written
+}
+
+///Writes a number in the given radix. Only exact numbers can be written in
+///a radix other than ten.
+pub fn to_str_radix(a: &NumericType, radix: uint) -> Result<StrBuf, StrBuf> {
+    match *a {
+        Integer(_) | BigInteger(_)  => Ok(a.to_big().to_str_radix(radix).to_strbuf()),
+        Rational(ref x)             => Ok(format!("{}/{}", x.numer().to_str_radix(radix),
+                                                  x.denom().to_str_radix(radix)).to_strbuf()),
+        _ if radix == 10            => Ok(format!("{}", a).to_strbuf()),
+        _   => Err(format!("{} can only be written in decimal!", a).to_strbuf())
+    }
+}
+
+///Converts a bignum of any size to floating point, which to_f64 won't do
+///once it no longer fits in 64 bits.
+fn big_to_float(x: &BigInt) -> f64 {
+    match x.to_f64() {
+        Some(x) => x,
+        None    => from_str::<f64>(x.to_str().as_slice()).unwrap()
+    }
+}
+
+///Converts two numbers to whichever of their kinds is higher up the tower.
+fn coerce(a: &NumericType, b: &NumericType) -> (NumericType, NumericType) {
+    let rank = max(a.rank(), b.rank());
+    (a.promote(rank), b.promote(rank))
+}
+
+///Rationals whose denominator is one are integers, and bignums which fit in
+///an int are ints.
+fn normalize(number: NumericType) -> NumericType {
+    match number {
+        Rational(x) => {
+            if x.is_integer() {
+                normalize(BigInteger(x.numer().clone()))
+            } else {
+                Rational(x)
+            }
+        }
+        BigInteger(x)   => match x.to_int() {
+            Some(x) => Integer(x),
+            None    => BigInteger(x)
+        },
+        number  => number
+    }
+}
+
+///Does an operation on two ints, falling back on bignums if the result
+///overflows.
+fn checked(x: int, y: int, op: |&int, &int| -> Option<int>,
+           big_op: |BigInt, BigInt| -> BigInt) -> NumericType {
+    match op(&x, &y) {
+        Some(answer)    => Integer(answer),
+        None            => normalize(BigInteger(big_op(x.to_bigint().unwrap(),
+                                                       y.to_bigint().unwrap())))
+    }
+}
+
+pub fn add(a: &NumericType, b: &NumericType) -> NumericType {
+    match coerce(a, b) {
+        (Integer(x), Integer(y))        => checked(x, y, |x, y| x.checked_add(y), |x, y| x + y),
+        (BigInteger(x), BigInteger(y))  => normalize(BigInteger(x + y)),
+        (Rational(x), Rational(y))      => normalize(Rational(x + y)),
+        (Floating(x), Floating(y))      => Floating(x + y),
+        (Complex(x), Complex(y))        => from_complex(x + y),
+        _                               => unreachable!()
+    }
+}
+
+pub fn sub(a: &NumericType, b: &NumericType) -> NumericType {
+    match coerce(a, b) {
+        (Integer(x), Integer(y))        => checked(x, y, |x, y| x.checked_sub(y), |x, y| x - y),
+        (BigInteger(x), BigInteger(y))  => normalize(BigInteger(x - y)),
+        (Rational(x), Rational(y))      => normalize(Rational(x - y)),
+        (Floating(x), Floating(y))      => Floating(x - y),
+        (Complex(x), Complex(y))        => from_complex(x - y),
+        _                               => unreachable!()
+    }
+}
+
+///The number with the opposite sign. Floats are negated directly, since
+///subtracting 0.0 from zero would lose the sign of a negative zero.
+pub fn negate(a: &NumericType) -> NumericType {
+    match *a {
+        Floating(x) => Floating(-x),
+        Complex(z)  => Complex(-z),
+        _           => sub(&Integer(0), a)
+    }
+}
+
+pub fn mul(a: &NumericType, b: &NumericType) -> NumericType {
+    match coerce(a, b) {
+        (Integer(x), Integer(y))        => checked(x, y, |x, y| x.checked_mul(y), |x, y| x * y),
+        (BigInteger(x), BigInteger(y))  => normalize(BigInteger(x * y)),
+        (Rational(x), Rational(y))      => normalize(Rational(x * y)),
+        (Floating(x), Floating(y))      => Floating(x * y),
+        (Complex(x), Complex(y))        => from_complex(x * y),
+        _                               => unreachable!()
+    }
+}
+
+///Dividing exact numbers gives an exact rational, so (/ 1 3) is 1/3.
+pub fn div(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    if b.is_exact() && b.is_zero() {
+        return Err("Division by zero!".to_strbuf())
+    }
+    match coerce(a, b) {
+        (Floating(x), Floating(y))  => Ok(Floating(x / y)),
+        (Complex(x), Complex(y))    => Ok(from_complex(x / y)),
+        _   => Ok(normalize(Rational(a.to_rational() / b.to_rational())))
+    }
+}
+
+///Divides one integer by another, giving the quotient and remainder. The
+///quotient is rounded towards negative infinity if `floor` is set, and
+///towards zero otherwise. Floating point numbers are allowed as long as
+///they are integers, and give floating point results.
+pub fn divide(a: &NumericType, b: &NumericType, floor: bool)
+                                    -> Result<(NumericType, NumericType), StrBuf> {
+    if b.is_zero() {
+        return Err("Division by zero!".to_strbuf())
+    }
+    match coerce(a, b) {
+        (Floating(x), Floating(y)) if x.fract() == 0.0 && y.fract() == 0.0 => {
+            let q = if floor { (x / y).floor() } else { (x / y).trunc() };
+            Ok((Floating(q), Floating(x - q * y)))
+        }
+        (Integer(_), Integer(_)) | (BigInteger(_), BigInteger(_))   => {
+            let (x, y) = (a.to_big(), b.to_big());
+            let (mut q, mut r) = (x / y, x % y);
+            if floor && !r.is_zero() && r.is_negative() != y.is_negative() {
+                q = q - One::one();
+                r = r + y;
+            }
+            Ok((normalize(BigInteger(q)), normalize(BigInteger(r))))
+        }
+        _   => Err(format!("Expected integers, got {} and {}", a, b).to_strbuf())
+    }
+}
+
+pub fn quotient(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    divide(a, b, false).map(|(q, _)| q)
+}
+
+///The remainder of truncating division, which has the sign of the dividend.
+pub fn remainder(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    divide(a, b, false).map(|(_, r)| r)
+}
+
+///The remainder of flooring division, which has the sign of the divisor.
+pub fn modulo(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    divide(a, b, true).map(|(_, r)| r)
+}
+
+///The value of an integer as a bignum, whether it's exact or not.
+fn integer_value(a: &NumericType) -> Result<BigInt, StrBuf> {
+    match *a {
+        Integer(_) | BigInteger(_)  => Ok(a.to_big()),
+        Floating(x) if x.fract() == 0.0 && !x.is_infinite() => Ok(try!(exact(a)).to_big()),
+        _   => Err(format!("Expected an integer, got {}", a).to_strbuf())
+    }
+}
+
+///Makes an answer inexact if either of the numbers it came from was.
+fn keep_exactness(a: &NumericType, b: &NumericType, answer: NumericType) -> NumericType {
+    if a.is_exact() && b.is_exact() {
+        answer
+    } else {
+        inexact(&answer)
+    }
+}
+
+pub fn gcd(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    let (mut x, mut y) = (try!(integer_value(a)).abs(), try!(integer_value(b)).abs());
+    while !y.is_zero() {
+        let r = x % y;
+        x = y;
+        y = r;
+    }
+    Ok(keep_exactness(a, b, normalize(BigInteger(x))))
+}
+
+pub fn lcm(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    let divisor = try!(integer_value(&try!(gcd(a, b))));
+    if divisor.is_zero() {
+        return Ok(keep_exactness(a, b, Integer(0)))
+    }
+    let product = (try!(integer_value(a)) * try!(integer_value(b))).abs();
+    Ok(keep_exactness(a, b, normalize(BigInteger(product / divisor))))
+}
+
+pub fn abs(a: &NumericType) -> Result<NumericType, StrBuf> {
+    match try!(compare(a, &Integer(0))) {
+        Some(Less)  => Ok(negate(a)),
+        _           => Ok(a.clone())
+    }
+}
+
+///The lesser (or greater, if `greatest` is set) of two reals. The answer
+///is inexact if either of them is.
+pub fn extremum(a: &NumericType, b: &NumericType, greatest: bool) -> Result<NumericType, StrBuf> {
+    let answer = match try!(compare(a, b)) {
+        Some(Less) if greatest      => b,
+        Some(Greater) if !greatest  => b,
+        _                           => a
+    };
+    Ok(keep_exactness(a, b, answer.clone()))
+}
+
+///Rounds a real to an integer, the way `rational` rounds an exact rational
+///and `float` rounds a floating point number. Integers are left as they are.
+fn integral(a: &NumericType, rational: |&BigRational| -> BigRational, float: |f64| -> f64)
+                                                            -> Result<NumericType, StrBuf> {
+    match *a {
+        Integer(_) | BigInteger(_)  => Ok(a.clone()),
+        Rational(ref x)             => Ok(normalize(Rational(rational(x)))),
+        Floating(x)                 => Ok(Floating(float(x))),
+        Complex(_)  => Err(format!("Expected a real number, got {}", a).to_strbuf())
+    }
+}
+
+pub fn floor(a: &NumericType) -> Result<NumericType, StrBuf> {
+    integral(a, |x| x.floor(), |x| x.floor())
+}
+
+pub fn ceiling(a: &NumericType) -> Result<NumericType, StrBuf> {
+    integral(a, |x| x.ceil(), |x| x.ceil())
+}
+
+pub fn truncate(a: &NumericType) -> Result<NumericType, StrBuf> {
+    integral(a, |x| x.trunc(), |x| x.trunc())
+}
+
+///Rounds to the nearest integer, and to the even one when halfway between
+///two.
+pub fn round(a: &NumericType) -> Result<NumericType, StrBuf> {
+    integral(a, |x| {
+        let floor = x.floor();
+        let half = Ratio::new(One::one(), 2i.to_bigint().unwrap());
+        let diff = *x - floor;
+        let two = 2i.to_bigint().unwrap();
+        if diff < half || (diff == half && (*floor.numer() % two).is_zero()) {
+            floor
+        } else {
+            floor + One::one()
+        }
+    }, |x| {
+        let floor = x.floor();
+        let diff = x - floor;
+        if diff < 0.5 || (diff == 0.5 && floor % 2.0 == 0.0) {
+            floor
+        } else {
+            floor + 1.0
+        }
+    })
+}
+
+///Whether an integer, exact or not, is even.
+pub fn is_even(a: &NumericType) -> Result<bool, StrBuf> {
+    let x = try!(integer_value(a));
+    Ok((x % 2i.to_bigint().unwrap()).is_zero())
+}
+
+///The largest integer whose square is no greater than `n`, found with
+///Newton's method.
+fn isqrt(n: &BigInt) -> BigInt {
+    let two = 2i.to_bigint().unwrap();
+    if *n < two {
+        return n.clone()
+    }
+    let mut x = n.clone();
+    let mut y = (x + *n / x) / two;
+    while y < x {
+        x = y;
+        y = (x + *n / x) / two;
+    }
+    x
+}
+
+///Complex results with no imaginary part are given as reals.
+fn from_complex(z: Complex64) -> NumericType {
+    if z.im == 0.0 {
+        Floating(z.re)
+    } else {
+        Complex(z)
+    }
+}
+
+fn complex_exp(z: Complex64) -> Complex64 {
+    let r = z.re.exp();
+    Complex64::new(r * z.im.cos(), r * z.im.sin())
+}
+
+///The principal value of the logarithm, whose imaginary part is the angle.
+fn complex_log(z: Complex64) -> Complex64 {
+    Complex64::new(z.norm().ln(), z.arg())
+}
+
+fn complex_sqrt(z: Complex64) -> Complex64 {
+    let (r, theta) = (z.norm().sqrt(), z.arg() / 2.0);
+    Complex64::new(r * theta.cos(), r * theta.sin())
+}
+
+fn complex_sin(z: Complex64) -> Complex64 {
+    Complex64::new(z.re.sin() * z.im.cosh(), z.re.cos() * z.im.sinh())
+}
+
+fn complex_cos(z: Complex64) -> Complex64 {
+    Complex64::new(z.re.cos() * z.im.cosh(), -z.re.sin() * z.im.sinh())
+}
+
+fn complex_tan(z: Complex64) -> Complex64 {
+    complex_sin(z) / complex_cos(z)
+}
+
+///asin z = -i log(iz + sqrt(1 - z^2))
+fn complex_asin(z: Complex64) -> Complex64 {
+    let (i, one) = (Complex64::new(0.0, 1.0), Complex64::new(1.0, 0.0));
+    -i * complex_log(i * z + complex_sqrt(one - z * z))
+}
+
+///acos z = pi/2 - asin z
+fn complex_acos(z: Complex64) -> Complex64 {
+    Complex64::new(Float::frac_pi_2(), 0.0) - complex_asin(z)
+}
+
+///atan z = i/2 (log(1 - iz) - log(1 + iz))
+fn complex_atan(z: Complex64) -> Complex64 {
+    let (i, one) = (Complex64::new(0.0, 1.0), Complex64::new(1.0, 0.0));
+    Complex64::new(0.0, 0.5) * (complex_log(one - i * z) - complex_log(one + i * z))
+}
+
+///Applies a function which has a real version, defined on the reals for
+///which it returns Some, and a complex version for everything else.
+fn transcendental(a: &NumericType, real: |f64| -> Option<f64>,
+                  complex: |Complex64| -> Complex64) -> NumericType {
+    match *a {
+        Complex(z)  => from_complex(complex(z)),
+        _           => match real(a.to_float()) {
+            Some(x) => Floating(x),
+            None    => from_complex(complex(a.to_complex()))
+        }
+    }
+}
+
+pub fn exp(a: &NumericType) -> NumericType {
+    transcendental(a, |x| Some(x.exp()), complex_exp)
+}
+
+///The natural logarithm. Negative numbers have complex logarithms.
+pub fn log(a: &NumericType) -> NumericType {
+    transcendental(a, |x| if x < 0.0 { None } else { Some(x.ln()) }, complex_log)
+}
+
+pub fn log_base(a: &NumericType, base: &NumericType) -> Result<NumericType, StrBuf> {
+    div(&log(a), &log(base))
+}
+
+pub fn sin(a: &NumericType) -> NumericType {
+    transcendental(a, |x| Some(x.sin()), complex_sin)
+}
+
+pub fn cos(a: &NumericType) -> NumericType {
+    transcendental(a, |x| Some(x.cos()), complex_cos)
+}
+
+pub fn tan(a: &NumericType) -> NumericType {
+    transcendental(a, |x| Some(x.tan()), complex_tan)
+}
+
+///Outside of [-1, 1] the arcsine is complex.
+pub fn asin(a: &NumericType) -> NumericType {
+    transcendental(a, |x| if x.abs() > 1.0 { None } else { Some(x.asin()) }, complex_asin)
+}
+
+pub fn acos(a: &NumericType) -> NumericType {
+    transcendental(a, |x| if x.abs() > 1.0 { None } else { Some(x.acos()) }, complex_acos)
+}
+
+pub fn atan(a: &NumericType) -> NumericType {
+    transcendental(a, |x| Some(x.atan()), complex_atan)
+}
+
+///The angle from the positive x axis to the point (x, y).
+pub fn atan2(y: &NumericType, x: &NumericType) -> Result<NumericType, StrBuf> {
+    match (y, x) {
+        (&Complex(_), _) | (_, &Complex(_)) => {
+            Err("atan with two arguments takes real numbers!".to_strbuf())
+        }
+        _   => Ok(Floating(y.to_float().atan2(&x.to_float())))
+    }
+}
+
+///The square root, which is exact when given an exact number with an exact
+///square root. Negative numbers have imaginary square roots.
+pub fn sqrt(a: &NumericType) -> Result<NumericType, StrBuf> {
+    match *a {
+        Complex(z)  => return Ok(from_complex(complex_sqrt(z))),
+        _           => { }
+    }
+    if try!(compare(a, &Integer(0))) == Some(Less) {
+        let root = try!(sqrt(&sub(&Integer(0), a)));
+        return Ok(Complex(Complex64::new(0.0, root.to_float())))
+    }
+    if a.is_exact() {
+        let x = a.to_rational();
+        let (n, d) = (isqrt(x.numer()), isqrt(x.denom()));
+        if n * n == *x.numer() && d * d == *x.denom() {
+            return Ok(normalize(Rational(Ratio::new(n, d))))
+        }
+    }
+    Ok(Floating(a.to_float().sqrt()))
+}
+
+///The largest integer whose square is no greater than a nonnegative exact
+///integer, and what's left over.
+pub fn exact_integer_sqrt(a: &NumericType) -> Result<(NumericType, NumericType), StrBuf> {
+    match *a {
+        Integer(_) | BigInteger(_) if !a.to_big().is_negative()  => {
+            let n = a.to_big();
+            let root = isqrt(&n);
+            let rest = n - root * root;
+            Ok((normalize(BigInteger(root)), normalize(BigInteger(rest))))
+        }
+        _   => Err(format!("Expected a nonnegative exact integer, got {}", a).to_strbuf())
+    }
+}
+
+///Raises `a` to the power `b`. An exact number raised to an exact integer
+///power is exact.
+pub fn expt(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    match *b {
+        Integer(_) | BigInteger(_) if a.is_exact()  => {
+            let exponent = b.to_big();
+            let n = match exponent.abs().to_uint() {
+                Some(n) => n,
+                None    => return Err("Exponent too large!".to_strbuf())
+            };
+            let power = normalize(Rational(pow(a.to_rational(), n)));
+            return if exponent.is_negative() { div(&Integer(1), &power) } else { Ok(power) }
+        }
+        _   => { }
+    }
+
+    //the logarithm of zero is infinite, but zero to any power whose real
+    //part is positive is zero; other powers are left to powf below
+    if a.is_zero() && (b.is_zero() || b.to_complex().re > 0.0) {
+        return Ok(if b.is_zero() { Floating(1.0) } else { Floating(0.0) })
+    }
+    match (a, b) {
+        (&Complex(_), _) | (_, &Complex(_))   => { }
+        _   => {
+            let (x, y) = (a.to_float(), b.to_float());
+            //only integer powers of negative numbers are real
+            if x >= 0.0 || y.fract() == 0.0 {
+                return Ok(Floating(x.powf(y)))
+            }
+        }
+    }
+    Ok(from_complex(complex_exp(b.to_complex() * complex_log(a.to_complex()))))
+}
+
+pub fn is_nan(a: &NumericType) -> bool {
+    match *a {
+        Floating(x) => x.is_nan(),
+        Complex(z)  => z.re.is_nan() || z.im.is_nan(),
+        _           => false
+    }
+}
+
+pub fn is_infinite(a: &NumericType) -> bool {
+    match *a {
+        Floating(x) => x.is_infinite(),
+        Complex(z)  => z.re.is_infinite() || z.im.is_infinite(),
+        _           => false
+    }
+}
+
+///The value of an exact integer as a bignum.
+fn exact_integer(a: &NumericType) -> Result<BigInt, StrBuf> {
+    match *a {
+        Integer(_) | BigInteger(_)  => Ok(a.to_big()),
+        _   => Err(format!("Expected an exact integer, got {}", a).to_strbuf())
+    }
+}
+
+fn unsigned(x: &BigInt) -> BigUint {
+    x.to_biguint().unwrap()
+}
+
+fn signed(x: BigUint) -> BigInt {
+    x.to_bigint().unwrap()
+}
+
+///~x, which in two's complement is -x - 1.
+fn complement(x: &BigInt) -> BigInt {
+    -*x - One::one()
+}
+
+//Bignums are combined bit by bit as if they were in two's complement,
+//where a negative number has infinitely many leading ones. Only the bits
+//of nonnegative numbers can be operated on directly, so negative numbers
+//are complemented first, using identities such as a & b = ~(~a | ~b).
+
+fn big_and(x: &BigInt, y: &BigInt) -> BigInt {
+    match (x.is_negative(), y.is_negative()) {
+        (false, false)  => signed(unsigned(x) & unsigned(y)),
+        //x & ~n = x - (x & n)
+        (false, true)   => *x - big_and(x, &complement(y)),
+        (true, false)   => big_and(y, x),
+        (true, true)    => complement(&big_or(&complement(x), &complement(y)))
+    }
+}
+
+fn big_or(x: &BigInt, y: &BigInt) -> BigInt {
+    match (x.is_negative(), y.is_negative()) {
+        (false, false)  => signed(unsigned(x) | unsigned(y)),
+        //x | ~n = ~(n & ~x) = ~(n - (n & x))
+        (false, true)   => {
+            let n = complement(y);
+            complement(&(n - big_and(&n, x)))
+        }
+        (true, false)   => big_or(y, x),
+        (true, true)    => complement(&big_and(&complement(x), &complement(y)))
+    }
+}
+
+fn big_xor(x: &BigInt, y: &BigInt) -> BigInt {
+    match (x.is_negative(), y.is_negative()) {
+        (false, false)  => big_or(x, y) - big_and(x, y),
+        (false, true)   => complement(&big_xor(x, &complement(y))),
+        (true, false)   => complement(&big_xor(&complement(x), y)),
+        (true, true)    => big_xor(&complement(x), &complement(y))
+    }
+}
+
+pub fn bitwise_and(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    match (a, b) {
+        (&Integer(x), &Integer(y))  => Ok(Integer(x & y)),
+        _   => Ok(normalize(BigInteger(big_and(&try!(exact_integer(a)),
+                                               &try!(exact_integer(b))))))
+    }
+}
+
+pub fn bitwise_or(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    match (a, b) {
+        (&Integer(x), &Integer(y))  => Ok(Integer(x | y)),
+        _   => Ok(normalize(BigInteger(big_or(&try!(exact_integer(a)),
+                                              &try!(exact_integer(b))))))
+    }
+}
+
+pub fn bitwise_xor(a: &NumericType, b: &NumericType) -> Result<NumericType, StrBuf> {
+    match (a, b) {
+        (&Integer(x), &Integer(y))  => Ok(Integer(x ^ y)),
+        _   => Ok(normalize(BigInteger(big_xor(&try!(exact_integer(a)),
+                                               &try!(exact_integer(b))))))
+    }
+}
+
+pub fn bitwise_not(a: &NumericType) -> Result<NumericType, StrBuf> {
+    match *a {
+        Integer(x)  => Ok(Integer(!x)),
+        _           => Ok(normalize(BigInteger(complement(&try!(exact_integer(a))))))
+    }
+}
+
+///Shifts left by `count` bits, or right if it's negative. Shifting right
+///rounds towards negative infinity, the way it would in two's complement.
+pub fn arithmetic_shift(a: &NumericType, count: &NumericType) -> Result<NumericType, StrBuf> {
+    let x = try!(exact_integer(a));
+    let shift = match try!(exact_integer(count)).to_int() {
+        Some(shift) => shift,
+        None        => return Err("Shift too large!".to_strbuf())
+    };
+    let scale = pow(2i.to_bigint().unwrap(), shift.abs() as uint);
+    if shift >= 0 {
+        Ok(normalize(BigInteger(x * scale)))
+    } else {
+        divide(&BigInteger(x), &BigInteger(scale), true).map(|(q, _)| q)
+    }
+}
+
+///How many bits are set in a nonnegative integer, or clear in a negative
+///one.
+pub fn bit_count(a: &NumericType) -> Result<NumericType, StrBuf> {
+    let x = try!(exact_integer(a));
+    let mut x = if x.is_negative() { complement(&x) } else { x };
+    let two = 2i.to_bigint().unwrap();
+    let mut count = 0;
+    while !x.is_zero() {
+        if !(x % two).is_zero() {
+            count += 1;
+        }
+        x = x / two;
+    }
+    Ok(Integer(count))
+}
+
+///Whether the bit `index` places from the least significant one is set.
+pub fn is_bit_set(index: &NumericType, a: &NumericType) -> Result<bool, StrBuf> {
+    if try!(compare(index, &Integer(0))) == Some(Less) {
+        return Err("Bit indices can't be negative!".to_strbuf())
+    }
+    let shifted = try!(arithmetic_shift(a, &sub(&Integer(0), index)));
+    is_even(&shifted).map(|even| !even)
+}
+
+///Numbers of any kinds are equal if they have the same value.
+pub fn equal(a: &NumericType, b: &NumericType) -> bool {
+    let (x, y) = coerce(a, b);
+    x == y
+}
+
+///Orders two real numbers, giving None if either is NaN. Complex numbers
+///have no order at all.
+pub fn compare(a: &NumericType, b: &NumericType) -> Result<Option<Ordering>, StrBuf> {
+    match coerce(a, b) {
+        (Integer(x), Integer(y))        => Ok(Some(x.cmp(&y))),
+        (BigInteger(x), BigInteger(y))  => Ok(Some(x.cmp(&y))),
+        (Rational(x), Rational(y))      => Ok(Some(x.cmp(&y))),
+        (Floating(x), Floating(y))      => {
+            if x < y {
+                Ok(Some(Less))
+            } else if x > y {
+                Ok(Some(Greater))
+            } else if x == y {
+                Ok(Some(Equal))
+            } else {
+                Ok(None)
+            }
+        }
+        _   => Err("Complex numbers can't be ordered!".to_strbuf())
+    }
+}
+
+///Converts a number to an exact one with the same value. Floating point
+///numbers are exactly a rational with a power of two for a denominator.
+pub fn exact(a: &NumericType) -> Result<NumericType, StrBuf> {
+    match *a {
+        Floating(x) => {
+            if x.is_nan() || x.is_infinite() {
+                return Err(format!("{} has no exact value!", a).to_strbuf())
+            }
+            let (mantissa, exponent, sign) = x.integer_decode();
+            let mantissa = mantissa.to_bigint().unwrap() * (sign as int).to_bigint().unwrap();
+            let scale = pow(2i.to_bigint().unwrap(), (exponent as int).abs() as uint);
+            let value = if exponent >= 0 {
+                Ratio::from_integer(mantissa * scale)
+            } else {
+                Ratio::new(mantissa, scale)
+            };
+            Ok(normalize(Rational(value)))
+        }
+        Complex(x)  => {
+            if x.im != 0.0 {
+                return Err(format!("{} has no exact value!", a).to_strbuf())
+            }
+            exact(&Floating(x.re))
+        }
+        _   => Ok(a.clone())
+    }
+}
+
+///Converts a number to floating point, unless it already is inexact.
+pub fn inexact(a: &NumericType) -> NumericType {
+    match *a {
+        Floating(_) | Complex(_)    => a.clone(),
+        _                           => Floating(a.to_float())
+    }
+}
+
+///Reads a number. It may be prefixed with #x, #o, #b or #d for its radix
+///and #e or #i for its exactness, and may be an integer, a decimal with an
+///optional exponent, a ratio such as 3/4, +inf.0, -inf.0, +nan.0 or a
+///complex number in rectangular form such as 1+2i or -i.
+pub fn parse(word: &str) -> Option<NumericType> {
+    parse_radix(word, 10)
+}
+
+///Reads a number in the given radix, unless a prefix says otherwise.
+pub fn parse_radix(word: &str, default_radix: uint) -> Option<NumericType> {
+    let mut word = word;
+    let mut radix = None;
+    let mut exactness = None;
+
+    while word.starts_with("#") {
+        if word.char_len() < 2 {
+            return None
+        }
+        match word.char_at(1).to_lowercase() {
+            'x' if radix.is_none()      => radix = Some(16),
+            'o' if radix.is_none()      => radix = Some(8),
+            'b' if radix.is_none()      => radix = Some(2),
+            'd' if radix.is_none()      => radix = Some(10),
+            'e' if exactness.is_none()  => exactness = Some(true),
+            'i' if exactness.is_none()  => exactness = Some(false),
+            _                           => return None
+        }
+        word = word.slice_from(2);
+    }
+
+    let radix = radix.unwrap_or(default_radix);
+    //going through a float would round an exact decimal such as #e0.1
+    if exactness == Some(true) && radix == 10 {
+        match parse_exact_decimal(word) {
+            Some(number)    => return Some(number),
+            None            => { }
+        }
+    }
+
+    let number = match parse_complex(word, radix) {
+        Some(number)    => number,
+        None            => return None
+    };
+    match exactness {
+        Some(true)  => exact(&number).ok(),
+        Some(false) => Some(inexact(&number)),
+        None        => Some(number)
+    }
+}
+
+///Reads a real number, or a complex one whose imaginary part follows the
+///last sign which doesn't belong to an exponent.
+fn parse_complex(word: &str, radix: uint) -> Option<NumericType> {
+    if !word.ends_with("i") {
+        return parse_real(word, radix)
+    }
+
+    let digits = word.slice_to(word.len() - 1);
+    let mut split = None;
+    for (idx, c) in digits.char_indices() {
+        let exponent = radix == 10 && idx > 0 && {
+            let previous = digits.char_at(idx - 1);
+            previous == 'e' || previous == 'E'
+        };
+        if (c == '+' || c == '-') && !exponent {
+            split = Some(idx);
+        }
+    }
+
+    let (real, imaginary) = match split {
+        Some(0)     => (Some(Integer(0)), parse_imaginary(digits, radix)),
+        Some(idx)   => (parse_real(digits.slice_to(idx), radix),
+                        parse_imaginary(digits.slice_from(idx), radix)),
+        None        => return None
+    };
+    match (real, imaginary) {
+        (Some(re), Some(im))    => Some(from_complex(Complex64::new(re.to_float(), im.to_float()))),
+        _                       => None
+    }
+}
+
+///An imaginary part of one can be written as just its sign, as in 1-i.
+fn parse_imaginary(word: &str, radix: uint) -> Option<NumericType> {
+    match word {
+        "+" => Some(Integer(1)),
+        "-" => Some(Integer(-1)),
+        _   => parse_real(word, radix)
+    }
+}
+
+///Reads an integer, a ratio, or in decimal, a number with a decimal point
+///or exponent. Signed infinities and NaN are read as floating point.
+fn parse_real(word: &str, radix: uint) -> Option<NumericType> {
+    let (negative, magnitude) = if word.starts_with("-") {
+        (true, word.slice_from(1))
+    } else if word.starts_with("+") {
+        (false, word.slice_from(1))
+    } else {
+        (false, word)
+    };
+
+    //infinities and NaN need a sign, so they can't be mistaken for symbols
+    if magnitude.len() != word.len() {
+        match magnitude {
+            "inf.0" if negative => return Some(Floating(Float::neg_infinity())),
+            "inf.0"             => return Some(Floating(Float::infinity())),
+            "nan.0"             => return Some(Floating(Float::nan())),
+            _                   => { }
+        }
+    }
+
+    //the sign is applied to each part as it's read, rather than by
+    //subtracting from zero, so that -0.0 stays negative
+    match magnitude.find('/') {
+        Some(idx)   => {
+            match (parse_digits(magnitude.slice_to(idx), radix),
+                   parse_digits(magnitude.slice_from(idx + 1), radix)) {
+                (Some(ref n), Some(ref d)) if !d.is_zero() => {
+                    let n = if negative { -*n } else { n.clone() };
+                    Some(normalize(Rational(Ratio::new(n, d.clone()))))
+                }
+                _   => None
+            }
+        }
+        None if radix == 10 && magnitude.chars().any(|c| c == '.' || c == 'e' || c == 'E') => {
+            match from_str::<f64>(magnitude) {
+                Some(x) if negative => Some(Floating(-x)),
+                Some(x)             => Some(Floating(x)),
+                None                => None
+            }
+        }
+        None        => match parse_digits(magnitude, radix) {
+            Some(x) if negative => Some(normalize(BigInteger(-x))),
+            Some(x)             => Some(normalize(BigInteger(x))),
+            None                => None
+        }
+    }
+}
+
+///Reads a decimal with a point or an exponent as the exact number it stands
+///for, so #e0.1 is 1/10. Anything else is left to parse_complex.
+fn parse_exact_decimal(word: &str) -> Option<NumericType> {
+    let (negative, magnitude) = if word.starts_with("-") {
+        (true, word.slice_from(1))
+    } else if word.starts_with("+") {
+        (false, word.slice_from(1))
+    } else {
+        (false, word)
+    };
+    if !magnitude.chars().any(|c| c == '.' || c == 'e' || c == 'E') {
+        return None
+    }
+
+    let (mantissa, exponent) = match magnitude.find(|c: char| c == 'e' || c == 'E') {
+        Some(idx)   => {
+            let exponent = magnitude.slice_from(idx + 1);
+            let exponent = if exponent.starts_with("+") { exponent.slice_from(1) } else { exponent };
+            match from_str::<int>(exponent) {
+                Some(exponent)  => (magnitude.slice_to(idx), exponent),
+                None            => return None
+            }
+        }
+        None        => (magnitude, 0)
+    };
+    let (whole, fraction) = match mantissa.find('.') {
+        Some(idx)   => (mantissa.slice_to(idx), mantissa.slice_from(idx + 1)),
+        None        => (mantissa, "")
+    };
+    let digits = match parse_digits(whole.to_strbuf().append(fraction).as_slice(), 10) {
+        Some(digits)    => if negative { -digits } else { digits },
+        None            => return None
+    };
+
+    //the digits after the point scale the significand down as much as the
+    //exponent scales it up
+    let scale = exponent - fraction.len() as int;
+    let power = pow(10i.to_bigint().unwrap(), scale.abs() as uint);
+    let value = if scale >= 0 {
+        Ratio::from_integer(digits * power)
+    } else {
+        Ratio::new(digits, power)
+    };
+    Some(normalize(Rational(value)))
+}
+
+///Reads an unsigned run of digits in the given radix.
+fn parse_digits(word: &str, radix: uint) -> Option<BigInt> {
+    if word.len() == 0 || !word.chars().all(|c| c.to_digit(radix).is_some()) {
+        return None
+    }
+    from_str_radix::<BigInt>(word, radix)
 }
@@ -1,22 +1,105 @@
 //! Operators operating operationally on operands optionally
 
+use super::list::CXRS;
+
 #[deriving(Show, Clone, Eq)]
 pub enum OperatorType {
     Constructor,
     Car,
     Cdr,
+    Cxr(uint), //an index into CXRS
     List,
+    SetCar,
+    SetCdr,
+    IsPair,
+    IsList,
+    Length,
+    Append,
+    Reverse,
+    ListTail,
+    ListRef,
+    ListCopy,
+    Map,
+    ForEach,
+    IsEq,
+    IsEqv,
+    IsEqual,
+    Apply,
+    Eval,
+    InteractionEnvironment,
+    SchemeReportEnvironment,
+    NullEnvironment,
     Print,
     Lambda,
     Define,
     Set,
     Quote,
     If,
+    Let,
+    LetStar,
+    Letrec,
+    LetrecStar,
+    Do,
+    Begin,
+    Cond,
+    Case,
+    When,
+    Unless,
     Add,
     Sub,
     Mul,
     Div,
     Rem,
+    Quotient,
+    Modulo,
+    FloorDiv,
+    TruncateDiv,
+    Gcd,
+    Lcm,
+    Abs,
+    Min,
+    Max,
+    Exact,
+    Inexact,
+    Floor,
+    Ceiling,
+    Round,
+    Truncate,
+    Sqrt,
+    ExactIntegerSqrt,
+    Expt,
+    Exp,
+    Log,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Square,
+    IsNan,
+    IsInfinite,
+    IsFinite,
+    IsZero,
+    IsPositive,
+    IsNegative,
+    IsOdd,
+    IsInteger,
+    IsRational,
+    IsReal,
+    IsComplex,
+    IsNumber,
+    IsExact,
+    IsInexact,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseNot,
+    ArithmeticShift,
+    BitCount,
+    IsBitSet,
+    NumberToString,
+    StringToNumber,
     IsEven,
     Lt,
     LtEq,
@@ -26,19 +109,112 @@ pub enum OperatorType {
     Gt,
     And,
     Or,
-    Null
+    Null,
+    CallCC,
+    DynamicWind,
+    Reset,
+    Shift,
+    DefineSyntax,
+    LetSyntax,
+    LetrecSyntax,
+    SyntaxRules,
+    DefineMacro,
+    Defmacro,
+    Gensym,
+    MacroExpand1,
+    MacroExpand,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing
 }
 
 pub fn from_str(s: &str) -> Option<OperatorType> {
+    match CXRS.iter().position(|&name| name == s) {
+        Some(index) => return Some(Cxr(index)),
+        None        => { }
+    }
+
     match s {
         "cons"  => Some(Constructor),
         "car"   => Some(Car),
         "cdr"   => Some(Cdr),
         "list"  => Some(List),
+        "set-car!"  => Some(SetCar),
+        "set-cdr!"  => Some(SetCdr),
+        "pair?"     => Some(IsPair),
+        "list?"     => Some(IsList),
+        "length"    => Some(Length),
+        "append"    => Some(Append),
+        "reverse"   => Some(Reverse),
+        "list-tail" => Some(ListTail),
+        "list-ref"  => Some(ListRef),
+        "list-copy" => Some(ListCopy),
+        "null?"     => Some(Null),
+        "map"       => Some(Map),
+        "for-each"  => Some(ForEach),
+        "eq?"       => Some(IsEq),
+        "eqv?"      => Some(IsEqv),
+        "equal?"    => Some(IsEqual),
+        "apply"     => Some(Apply),
+        "eval"      => Some(Eval),
+        "interaction-environment"   => Some(InteractionEnvironment),
+        "scheme-report-environment" => Some(SchemeReportEnvironment),
+        "null-environment"          => Some(NullEnvironment),
         "print" => Some(Print),
         "lambda"=> Some(Lambda),
         "define"=> Some(Define),
-        "set"   => Some(Set),
+        "set!"  => Some(Set),
+        "remainder" => Some(Rem),
+        "quotient"  => Some(Quotient),
+        "modulo"    => Some(Modulo),
+        "floor/"    => Some(FloorDiv),
+        "truncate/" => Some(TruncateDiv),
+        "gcd"       => Some(Gcd),
+        "lcm"       => Some(Lcm),
+        "abs"       => Some(Abs),
+        "min"       => Some(Min),
+        "max"       => Some(Max),
+        "exact"     => Some(Exact),
+        "inexact"   => Some(Inexact),
+        "floor"     => Some(Floor),
+        "ceiling"   => Some(Ceiling),
+        "round"     => Some(Round),
+        "truncate"  => Some(Truncate),
+        "sqrt"      => Some(Sqrt),
+        "exact-integer-sqrt"=> Some(ExactIntegerSqrt),
+        "expt"      => Some(Expt),
+        "exp"       => Some(Exp),
+        "log"       => Some(Log),
+        "sin"       => Some(Sin),
+        "cos"       => Some(Cos),
+        "tan"       => Some(Tan),
+        "asin"      => Some(Asin),
+        "acos"      => Some(Acos),
+        "atan"      => Some(Atan),
+        "square"    => Some(Square),
+        "nan?"      => Some(IsNan),
+        "infinite?" => Some(IsInfinite),
+        "finite?"   => Some(IsFinite),
+        "zero?"     => Some(IsZero),
+        "positive?" => Some(IsPositive),
+        "negative?" => Some(IsNegative),
+        "odd?"      => Some(IsOdd),
+        "integer?"  => Some(IsInteger),
+        "rational?" => Some(IsRational),
+        "real?"     => Some(IsReal),
+        "complex?"  => Some(IsComplex),
+        "number?"   => Some(IsNumber),
+        "exact?"    => Some(IsExact),
+        "inexact?"  => Some(IsInexact),
+        "bitwise-and"=> Some(BitwiseAnd),
+        "bitwise-or"=> Some(BitwiseOr),
+        "bitwise-xor"=> Some(BitwiseXor),
+        "bitwise-not"=> Some(BitwiseNot),
+        "arithmetic-shift"=> Some(ArithmeticShift),
+        "bit-count" => Some(BitCount),
+        "bit-set?"  => Some(IsBitSet),
+        "number->string"=> Some(NumberToString),
+        "string->number"=> Some(StringToNumber),
         "even?" => Some(IsEven),
         "+"     => Some(Add),
         "-"     => Some(Sub),
@@ -51,9 +227,204 @@ pub fn from_str(s: &str) -> Option<OperatorType> {
         ">="    => Some(GtEq),
         ">"     => Some(Gt),
         "if"    => Some(If),
+        "let"   => Some(Let),
+        "let*"  => Some(LetStar),
+        "letrec"=> Some(Letrec),
+        "letrec*"=> Some(LetrecStar),
+        "do"    => Some(Do),
+        "begin" => Some(Begin),
+        "cond"  => Some(Cond),
+        "case"  => Some(Case),
+        "when"  => Some(When),
+        "unless"=> Some(Unless),
         "and"   => Some(And),
         "or"    => Some(Or),
         "quote" => Some(Quote),
+        "call-with-current-continuation" | "call/cc"    => Some(CallCC),
+        "dynamic-wind"  => Some(DynamicWind),
+        "reset" => Some(Reset),
+        "shift" => Some(Shift),
+        "define-syntax" => Some(DefineSyntax),
+        "let-syntax"    => Some(LetSyntax),
+        "letrec-syntax" => Some(LetrecSyntax),
+        "syntax-rules"  => Some(SyntaxRules),
+        "define-macro"  => Some(DefineMacro),
+        "defmacro"      => Some(Defmacro),
+        "gensym"        => Some(Gensym),
+        "macroexpand-1" => Some(MacroExpand1),
+        "macroexpand"   => Some(MacroExpand),
+        "quasiquote"    => Some(Quasiquote),
+        "unquote"       => Some(Unquote),
+        "unquote-splicing"  => Some(UnquoteSplicing),
         _       => None
     }
 }
+
+///The operators which are procedures rather than special forms, other than
+///the compositions of car and cdr. They're bound in the global environment
+///under their names, so they can be shadowed or redefined like any other
+///variable.
+pub static PROCEDURES: &'static [OperatorType] = &[
+    Constructor, Car, Cdr, List, SetCar, SetCdr, IsPair, IsList, Length, Append, Reverse,
+    ListTail, ListRef, ListCopy, Null, Map, ForEach, IsEq, IsEqv, IsEqual, Apply, Eval,
+    InteractionEnvironment, SchemeReportEnvironment, NullEnvironment, Print,
+    Add, Sub, Mul, Div, Rem, Quotient, Modulo, FloorDiv, TruncateDiv, Gcd, Lcm, Abs, Min,
+    Max, Exact, Inexact, Floor, Ceiling, Round, Truncate, Sqrt, ExactIntegerSqrt, Expt,
+    Exp, Log, Sin, Cos, Tan, Asin, Acos, Atan, Square, IsNan, IsInfinite, IsFinite,
+    IsZero, IsPositive, IsNegative, IsOdd, IsEven, IsInteger, IsRational, IsReal,
+    IsComplex, IsNumber, IsExact, IsInexact, BitwiseAnd, BitwiseOr, BitwiseXor,
+    BitwiseNot, ArithmeticShift, BitCount, IsBitSet, NumberToString, StringToNumber,
+    Lt, LtEq, Eq, NEq, GtEq, Gt, CallCC, DynamicWind, Gensym, MacroExpand1, MacroExpand
+];
+
+///The name an operator is written as in source.
+pub fn to_str(op: OperatorType) -> &'static str {
+    match op {
+        Constructor => "cons",
+        Car     => "car",
+        Cdr     => "cdr",
+        Cxr(index)  => CXRS[index],
+        List    => "list",
+        SetCar      => "set-car!",
+        SetCdr      => "set-cdr!",
+        IsPair      => "pair?",
+        IsList      => "list?",
+        Length      => "length",
+        Append      => "append",
+        Reverse     => "reverse",
+        ListTail    => "list-tail",
+        ListRef     => "list-ref",
+        ListCopy    => "list-copy",
+        Map         => "map",
+        ForEach     => "for-each",
+        IsEq        => "eq?",
+        IsEqv       => "eqv?",
+        IsEqual     => "equal?",
+        Apply       => "apply",
+        Eval        => "eval",
+        InteractionEnvironment  => "interaction-environment",
+        SchemeReportEnvironment => "scheme-report-environment",
+        NullEnvironment         => "null-environment",
+        Print   => "print",
+        Lambda  => "lambda",
+        Define  => "define",
+        Set     => "set!",
+        Quote   => "quote",
+        If      => "if",
+        Let     => "let",
+        LetStar => "let*",
+        Letrec  => "letrec",
+        LetrecStar=> "letrec*",
+        Do      => "do",
+        Begin   => "begin",
+        Cond    => "cond",
+        Case    => "case",
+        When    => "when",
+        Unless  => "unless",
+        Add     => "+",
+        Sub     => "-",
+        Mul     => "*",
+        Div     => "/",
+        Rem     => "remainder",
+        Quotient    => "quotient",
+        Modulo      => "modulo",
+        FloorDiv    => "floor/",
+        TruncateDiv => "truncate/",
+        Gcd         => "gcd",
+        Lcm         => "lcm",
+        Abs         => "abs",
+        Min         => "min",
+        Max         => "max",
+        Exact       => "exact",
+        Inexact     => "inexact",
+        Floor       => "floor",
+        Ceiling     => "ceiling",
+        Round       => "round",
+        Truncate    => "truncate",
+        Sqrt        => "sqrt",
+        ExactIntegerSqrt=> "exact-integer-sqrt",
+        Expt        => "expt",
+        Exp         => "exp",
+        Log         => "log",
+        Sin         => "sin",
+        Cos         => "cos",
+        Tan         => "tan",
+        Asin        => "asin",
+        Acos        => "acos",
+        Atan        => "atan",
+        Square      => "square",
+        IsNan       => "nan?",
+        IsInfinite  => "infinite?",
+        IsFinite    => "finite?",
+        IsZero      => "zero?",
+        IsPositive  => "positive?",
+        IsNegative  => "negative?",
+        IsOdd       => "odd?",
+        IsInteger   => "integer?",
+        IsRational  => "rational?",
+        IsReal      => "real?",
+        IsComplex   => "complex?",
+        IsNumber    => "number?",
+        IsExact     => "exact?",
+        IsInexact   => "inexact?",
+        BitwiseAnd  => "bitwise-and",
+        BitwiseOr   => "bitwise-or",
+        BitwiseXor  => "bitwise-xor",
+        BitwiseNot  => "bitwise-not",
+        ArithmeticShift=> "arithmetic-shift",
+        BitCount    => "bit-count",
+        IsBitSet    => "bit-set?",
+        NumberToString=> "number->string",
+        StringToNumber=> "string->number",
+        IsEven  => "even?",
+        Lt      => "<",
+        LtEq    => "<=",
+        Eq      => "=",
+        NEq     => "!=",
+        GtEq    => ">=",
+        Gt      => ">",
+        And     => "and",
+        Or      => "or",
+        Null    => "null?",
+        CallCC  => "call-with-current-continuation",
+        DynamicWind => "dynamic-wind",
+        Reset   => "reset",
+        Shift   => "shift",
+        DefineSyntax    => "define-syntax",
+        LetSyntax       => "let-syntax",
+        LetrecSyntax    => "letrec-syntax",
+        SyntaxRules     => "syntax-rules",
+        DefineMacro     => "define-macro",
+        Defmacro        => "defmacro",
+        Gensym          => "gensym",
+        MacroExpand1    => "macroexpand-1",
+        MacroExpand     => "macroexpand",
+        Quasiquote      => "quasiquote",
+        Unquote         => "unquote",
+        UnquoteSplicing => "unquote-splicing"
+    }
+}
+
+///The least and greatest number of arguments an operator takes when it's
+///applied, with no greatest if it takes any number more. Special forms
+///aren't applied to arguments, so they have no arity.
+pub fn arity(op: OperatorType) -> Option<(uint, Option<uint>)> {
+    match op {
+        Lambda | Define | Set | Quote | If | Let | LetStar | Letrec | LetrecStar |
+        Do | Begin | Cond | Case | When | Unless | And | Or | Reset | Shift |
+        DefineSyntax | LetSyntax | LetrecSyntax | SyntaxRules | DefineMacro |
+        Defmacro | Quasiquote | Unquote | UnquoteSplicing  => None,
+        List | Append | Add | Mul | Gcd | Lcm | BitwiseAnd | BitwiseOr |
+        BitwiseXor | Lt | LtEq | Eq | NEq | GtEq | Gt  => Some((0, None)),
+        Sub | Div | Min | Max   => Some((1, None)),
+        Map | ForEach | Apply   => Some((2, None)),
+        InteractionEnvironment  => Some((0, Some(0))),
+        Gensym  => Some((0, Some(1))),
+        Log | Atan | NumberToString | StringToNumber    => Some((1, Some(2))),
+        Constructor | SetCar | SetCdr | ListTail | ListRef | IsEq | IsEqv |
+        IsEqual | Eval | Rem | Quotient | Modulo | FloorDiv | TruncateDiv | Expt |
+        ArithmeticShift | IsBitSet  => Some((2, Some(2))),
+        DynamicWind => Some((3, Some(3))),
+        _       => Some((1, Some(1)))
+    }
+}
@@ -0,0 +1,136 @@
+;;; The prelude, defined in every global environment before anything else is
+;;; evaluated. It has the list procedures of SRFI-1 which aren't built in;
+;;; map and for-each are, since they take any number of lists.
+
+(define filter
+  (lambda (keep? xs)
+    (let loop ((xs xs) (kept '()))
+      (cond ((null? xs) (reverse kept))
+            ((keep? (car xs)) (loop (cdr xs) (cons (car xs) kept)))
+            (else (loop (cdr xs) kept))))))
+
+(define remove
+  (lambda (drop? xs)
+    (filter (lambda (x) (if (drop? x) #f #t)) xs)))
+
+;; a list of the elements which satisfy keep?, and a list of those which
+;; don't, just as floor/ gives its quotient and remainder
+(define partition
+  (lambda (keep? xs)
+    (let loop ((xs xs) (kept '()) (dropped '()))
+      (cond ((null? xs) (list (reverse kept) (reverse dropped)))
+            ((keep? (car xs)) (loop (cdr xs) (cons (car xs) kept) dropped))
+            (else (loop (cdr xs) kept (cons (car xs) dropped)))))))
+
+(define fold-left
+  (lambda (combine initial xs)
+    (let loop ((xs xs) (acc initial))
+      (if (null? xs)
+          acc
+          (loop (cdr xs) (combine acc (car xs)))))))
+
+(define fold-right
+  (lambda (combine initial xs)
+    (fold-left (lambda (acc x) (combine x acc)) initial (reverse xs))))
+
+;; like fold-left, with the first element as the initial value and the
+;; element before the accumulator; initial is only for the empty list
+(define reduce
+  (lambda (combine initial xs)
+    (if (null? xs)
+        initial
+        (fold-left (lambda (acc x) (combine x acc)) (car xs) (cdr xs)))))
+
+;; delete, delete-duplicates, member and assoc compare with equal? unless
+;; they're given another comparison
+(define delete
+  (lambda (x xs . compare)
+    (let ((same? (if (null? compare) equal? (car compare))))
+      (remove (lambda (y) (same? x y)) xs))))
+
+;; the first of each group of equal elements is kept, where it was
+(define delete-duplicates
+  (lambda (xs . compare)
+    (let ((same? (if (null? compare) equal? (car compare))))
+      (let loop ((xs xs) (kept '()))
+        (if (null? xs)
+            (reverse kept)
+            (loop (delete (car xs) (cdr xs) same?) (cons (car xs) kept)))))))
+
+(define find-tail
+  (lambda (found? xs)
+    (cond ((null? xs) #f)
+          ((found? (car xs)) xs)
+          (else (find-tail found? (cdr xs))))))
+
+(define find
+  (lambda (found? xs)
+    (let ((tail (find-tail found? xs)))
+      (if tail (car tail) #f))))
+
+(define any
+  (lambda (holds? xs)
+    (if (null? xs)
+        #f
+        (or (holds? (car xs)) (any holds? (cdr xs))))))
+
+;; the value of holds? for the last element, or #t for the empty list
+(define every
+  (lambda (holds? xs)
+    (cond ((null? xs) #t)
+          ((null? (cdr xs)) (holds? (car xs)))
+          ((holds? (car xs)) (every holds? (cdr xs)))
+          (else #f))))
+
+;; (iota count [start [step]])
+(define iota
+  (lambda (count . options)
+    (let ((start (if (null? options) 0 (car options)))
+          (step (if (or (null? options) (null? (cdr options))) 1 (cadr options))))
+      (let loop ((i (- count 1)) (numbers '()))
+        (if (< i 0)
+            numbers
+            (loop (- i 1) (cons (+ start (* i step)) numbers)))))))
+
+(define take
+  (lambda (xs k)
+    (let loop ((xs xs) (k k) (taken '()))
+      (if (= k 0)
+          (reverse taken)
+          (loop (cdr xs) (- k 1) (cons (car xs) taken))))))
+
+(define drop
+  (lambda (xs k)
+    (list-tail xs k)))
+
+(define last
+  (lambda (xs)
+    (if (null? (cdr xs))
+        (car xs)
+        (last (cdr xs)))))
+
+(define member
+  (lambda (x xs . compare)
+    (let ((same? (if (null? compare) equal? (car compare))))
+      (find-tail (lambda (y) (same? x y)) xs))))
+
+(define memv
+  (lambda (x xs)
+    (find-tail (lambda (y) (eqv? x y)) xs)))
+
+(define memq
+  (lambda (x xs)
+    (find-tail (lambda (y) (eq? x y)) xs)))
+
+(define assoc
+  (lambda (key alist . compare)
+    (let ((same? (if (null? compare) equal? (car compare))))
+      (find (lambda (entry) (same? key (car entry))) alist))))
+
+(define assv
+  (lambda (key alist)
+    (find (lambda (entry) (eqv? key (car entry))) alist)))
+
+(define assq
+  (lambda (key alist)
+    (find (lambda (entry) (eq? key (car entry))) alist)))
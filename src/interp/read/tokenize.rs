@@ -1,7 +1,9 @@
 //! Tokenizes input strings.
 
+use std::sync::atomics::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+
 use super::super::basictype::{BasicType, Boolean, Character, Number, String};
-use super::super::basictype::{Floating, Integer};
+use super::super::number;
 use super::super::operator::OperatorType;
 use super::super::operator;
 
@@ -13,20 +15,83 @@ pub enum Token {
     Operator(OperatorType),
     Literal(BasicType),
     Symbol(StrBuf), //Can be a variable or part of an enumeration
+    QuoteMark,
+    Backquote,
+    Comma,
+    CommaAt, //the ,@ of unquote-splicing
+    Dot, //separates the last dorsal region of an improper list
     Invalid,
     Whitespace //only used internally in iterators
 }
 
-#[deriving(Copy, Clone)]
+///A line and column in the source text, both counted from one, and the
+///text they're in. Forms can outlive the input they were read from, in the
+///bodies of procedures, so a position only means anything in its own text.
+#[deriving(Show, Clone, Eq)]
+pub struct Position {
+    pub line: uint,
+    pub column: uint,
+    pub source: uint
+}
+
+///A token along with the position of its first character.
+#[deriving(Show, Eq)]
+pub struct TokenAndPos {
+    pub tok: Token,
+    pub pos: Position
+}
+
+///Counts the texts tokenized, so that each gets a number of its own.
+static mut SOURCES: AtomicUint = INIT_ATOMIC_UINT;
+
+#[deriving(Clone)]
 pub struct TokenStream {
     pub string_slice: StrBuf,
     pub string_index: uint,
+    pub line: uint,
+    pub column: uint,
+    pub source: uint
+}
+
+impl TokenStream {
+    pub fn new(string_slice: StrBuf) -> TokenStream {
+        let source = unsafe { SOURCES.fetch_add(1, SeqCst) + 1 };
+        TokenStream {
+            string_slice: string_slice,
+            string_index: 0,
+            line: 1,
+            column: 1,
+            source: source
+        }
+    }
+
+    ///The position of the next character to be consumed.
+    pub fn position(&self) -> Position {
+        Position { line: self.line, column: self.column, source: self.source }
+    }
+
+    ///Moves forward by `len` bytes, keeping track of lines and columns.
+    fn advance(&mut self, len: uint) {
+        let (mut line, mut column) = (self.line, self.column);
+        for c in self.string_slice.as_slice().slice(self.string_index,
+                                            self.string_index + len).chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        self.line = line;
+        self.column = column;
+        self.string_index += len;
+    }
 }
 
 pub trait TokenIterator<TokenStream> {
     fn next_token_type(&self) -> (Option<Token>, uint);
-    fn next(&mut self) -> Option<Token>;
-    fn peek(&mut self) -> Option<Token>; //pesky whitespace
+    fn next(&mut self) -> Option<TokenAndPos>;
+    fn peek(&mut self) -> Option<TokenAndPos>; //pesky whitespace
 }
 
 impl TokenIterator<Token> for TokenStream {
@@ -42,28 +107,55 @@ impl TokenIterator<Token> for TokenStream {
                 ')' => {
                     return (Some(RParen), 1)
                 },
-                ' ' => {
+                ' ' | '\t' | '\n' | '\r' => {
                     return (Some(Whitespace), 1)
                 },
                 '\''=> {
-                    return (Some(Operator(operator::Quote)), 1)
+                    return (Some(QuoteMark), 1)
+                },
+                '`' => {
+                    return (Some(Backquote), 1)
+                },
+                ';' => {
+                    //a comment runs to the end of the line
+                    let rest = self.string_slice.as_slice().slice_from(self.string_index);
+                    return (Some(Whitespace), rest.find('\n').unwrap_or(rest.len()))
+                },
+                ',' => {
+                    if self.string_slice.as_slice().slice_from(self.string_index + 1)
+                                                    .starts_with("@") {
+                        return (Some(CommaAt), 2)
+                    }
+                    return (Some(Comma), 1)
                 },
                 _   => {},
             }
 
             let word = self.string_slice.as_slice().slice_from(
                                     self.string_index).words().next().unwrap();
-            //discard dangling parens
-            let word = word.slice(0, word.find(|c: char| c == ')' || c == '(').unwrap_or(word.len()));
+            //discard dangling parens and comments
+            let word = word.slice(0, word.find(|c: char| c == ')' || c == '(' || c == ';')
+                                         .unwrap_or(word.len()));
 
+            //only the special forms are keywords; the built in procedures
+            //are variables bound in the global environment
             match operator::from_str(word) {
-                Some(op_type) => {
+                Some(op_type) if operator::arity(op_type).is_none() => {
                     return (Some(Operator(op_type)), word.len())
                 }
+                Some(_) => {
+                    return (Some(Symbol(word.to_strbuf())), word.len())
+                }
                 None    => { } //do nothing
             }
 
             match word {
+                "."     => {
+                    return (Some(Dot), word.len())
+                }
+                "..." | "=>"    => {
+                    return (Some(Symbol(word.to_strbuf())), word.len())
+                }
                 "#t"    => {
                     return (Some(Literal(Boolean(true))), word.len())
                 }
@@ -118,86 +210,33 @@ impl TokenIterator<Token> for TokenStream {
             }
 
             //Numeric literals
-            let mut neg_counter = 0;
-            let mut radix_counter = 0;
-            let mut literal_flag = false;
-            let mut literal_spec = 'i';
-
-            for c in word.chars() {
-                match c {
-                    '0'..'9'    => { }
-                    '-'         => neg_counter += 1,
-                    '.'         => radix_counter += 1,
-                    'i' | 'f'   => {
-                        if literal_flag == true {
-                            return (Some(Invalid), 0)
-                        } else {
-                            literal_flag = true;
-                            literal_spec = c;
-                        }
-                    }
-                    _           => {
-                        return (Some(Invalid), 0)
-                    }
-                }
+            match number::parse(word) {
+                Some(x) => (Some(Literal(Number(x))), word.len()),
+                None    => (Some(Invalid), 0)
             }
-
-            //formatted literal
-            if literal_flag == true {
-                let token = match literal_spec {
-                    'i' => match from_str::<int>(word) {
-                        Some(x) => Some(Literal(Number(Integer(x)))),
-                        None    => Some(Invalid)
-                    },
-                    'f' => match from_str::<f64>(word) {
-                        Some(x) => Some(Literal(Number(Floating(x)))),
-                        None    => Some(Invalid)
-                    },
-                    _   => Some(Invalid)
-                };
-
-                return (token, word.len())
-            }
-
-            //time to play guess the type
-            let token = match (neg_counter, radix_counter) {
-                (1, 0) | (0, 0) => {
-                    match from_str::<int>(word) {
-                        Some(x) => Some(Literal(Number(Integer(x)))),
-                        None    => Some(Invalid)
-                    }
-                },
-                (0, 1) | (1, 1) => {
-                    match from_str::<f64>(word) {
-                        Some(x) => Some(Literal(Number(Floating(x)))),
-                        None    => Some(Invalid)
-                    }
-                }
-
-                _   => Some(Invalid)
-            };
-
-            return (token, word.len())
         }
     }
 
-    fn next(&mut self) -> Option<Token> {
+    fn next(&mut self) -> Option<TokenAndPos> {
         loop {
+            let pos = self.position();
             let (token, advance) = self.next_token_type();
-            self.string_index += advance;
-            if token != Some(Whitespace) { //guaranteed on None.
-                return token
+            self.advance(advance);
+            match token {
+                Some(Whitespace)    => { }
+                Some(tok)           => return Some(TokenAndPos { tok: tok, pos: pos }),
+                None                => return None
             }
         }
     }
 
-    fn peek(&mut self) -> Option<Token> {
+    fn peek(&mut self) -> Option<TokenAndPos> {
         loop {
             let (token, advance) = self.next_token_type();
-            if token != Some(Whitespace) {
-                return token
-            } else {
-                self.string_index += advance
+            match token {
+                Some(Whitespace)    => self.advance(advance),
+                Some(tok)           => return Some(TokenAndPos { tok: tok, pos: self.position() }),
+                None                => return None
             }
         }
     }
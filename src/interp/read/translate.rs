@@ -1,83 +1,90 @@
 //Translate takes a set of tokens and constructs from them an S Expression.
 
 use super::tokenize;
-use super::tokenize::{Token, TokenStream, TokenIterator, LParen, RParen};
-use super::super::{SExpr, Data, Cons, Nil, DataType, Operator, Literal, Variable};
-use super::super::basictype;
-use super::super::basictype::Procedure;
-use super::super::operator::Lambda;
-use super::super::eval::eval;
-use super::super::Environment;
+use super::tokenize::{TokenAndPos, TokenStream, TokenIterator, Position};
+use super::tokenize::{LParen, RParen, QuoteMark, Backquote, Comma, CommaAt, Dot};
+use super::super::{SExpr, Data, Cons, Nil, Operator, Literal, Variable};
+use super::super::{RispError, cons, vec_to_improper_list};
+use super::super::operator::{OperatorType, Quote, Quasiquote, Unquote, UnquoteSplicing};
 
 static TRANSFAIL: &'static str = "Failed to translate: malformed expression!";
 
-pub fn build_lambda(mut tokens: &mut TokenStream) -> Result<SExpr, StrBuf> {
+///Parse forms an SExpr recursively from a stream of tokens, one datum at a
+///time. Returns None once the stream is exhausted.
+pub fn parse(mut tokens: &mut TokenStream) -> Result<Option<SExpr>, RispError> {
     match tokens.next() {
-        Some(LParen)    => { } //good
-        _   =>  return Err("Improper lambda expression!".to_strbuf())
+        Some(token) => read_datum(tokens, token).map(|datum| Some(datum)),
+        None        => Ok(None)
     }
+}
 
-    let symbol = match tokens.next() {
-        Some(tokenize::Symbol(x)) => Variable(x),
-        _   => return Err("Improper lambda expression!".to_strbuf())
-    };
-
-    match tokens.next() {
-        Some(RParen)    => { } //good
-        _   => return Err("Improper lambda expression!".to_strbuf())
+///Reads the datum which begins with `token`.
+fn read_datum(mut tokens: &mut TokenStream, token: TokenAndPos) -> Result<SExpr, RispError> {
+    let TokenAndPos { tok, pos } = token;
+    match tok {
+        LParen      => read_list(tokens, pos),
+        QuoteMark   => read_abbreviation(tokens, Quote, pos),
+        Backquote   => read_abbreviation(tokens, Quasiquote, pos),
+        Comma       => read_abbreviation(tokens, Unquote, pos),
+        CommaAt     => read_abbreviation(tokens, UnquoteSplicing, pos),
+        tokenize::Operator(op)  => Ok(Data(Operator(op))),
+        tokenize::Literal(ty)   => Ok(Data(Literal(ty))),
+        tokenize::Symbol(x)     => Ok(Data(Variable(x))),
+        RParen  => Err(RispError::new("Unexpected closing paren!".to_strbuf(), Some(pos))),
+        _       => Err(RispError::new(TRANSFAIL.to_strbuf(), Some(pos)))
     }
+}
+
+///Reads a datum which must be present, such as the one following a quote
+///mark at `pos`.
+fn read_required(mut tokens: &mut TokenStream, pos: Position) -> Result<SExpr, RispError> {
     match tokens.next() {
-        Some(LParen)    => { } //good
-        _   => return Err("Improper lambda expression!".to_strbuf())
+        Some(token) => read_datum(tokens, token),
+        None        => Err(RispError::new(TRANSFAIL.to_strbuf(), Some(pos)))
     }
+}
 
-    let car = match tokens.next() {
-        Some(tokenize::Operator(op))    => super::super::Operator(op),
-        _   => return Err("sorry ._.".to_strbuf())
-    };
-
-
-    let mut procedure = Cons(box Data(symbol), box Data(car));
-    loop {
-        match tokens.next() {
-            Some(RParen)    => return Ok(procedure),
-            Some(tokenize::Operator(op))=> procedure = Cons(box procedure, box Data(Operator(op))),
-            Some(tokenize::Literal(ty)) => procedure = Cons(box procedure, box Data(Literal(ty))),
-            Some(tokenize::Symbol(sym)) => procedure = Cons(box procedure, box Data(Variable(sym))),
-            _   => return Err("Nope".to_strbuf())
-        }
-    }
+///Reads the datum following a quote mark, backquote, comma or comma at,
+///which at `pos` abbreviates `(op datum)`.
+fn read_abbreviation(mut tokens: &mut TokenStream, op: OperatorType,
+                     pos: Position) -> Result<SExpr, RispError> {
+    let quoted = try!(read_required(tokens, pos));
+    Ok(Cons(box Data(Operator(op)), box cons(quoted, Nil), Some(pos)))
 }
 
-///Parse forms an SExpr recursively from an array of tokens
-pub fn parse(mut tokens: &mut TokenStream, mut environment: &mut Environment) 
-                                            -> Result<Vec<DataType>, StrBuf> {
-    let mut terms: Vec<DataType> = Vec::new();
+///Reads the elements of a list whose opening paren is at `pos`, up to and
+///including its closing paren. A dot before the last element makes that
+///element the dorsal region of the final cell.
+fn read_list(mut tokens: &mut TokenStream, pos: Position) -> Result<SExpr, RispError> {
+    let mut terms = Vec::new();
+    let mut tail = Nil;
 
     loop {
-        let token = tokens.next();
-        match token {
-            Some(RParen)    => return Ok(terms),
-            Some(LParen)    => {
-                let inner_terms = try!(parse(tokens, environment));
-                let term = try!(eval(&inner_terms, environment));
-                terms.push(Literal(term));
+        let token = match tokens.next() {
+            Some(token) => token,
+            None        => return Err(RispError::new("Unbalanced parens!".to_strbuf(), Some(pos)))
+        };
+        match token.tok {
+            RParen  => break,
+            Dot     => {
+                if terms.len() == 0 {
+                    return Err(RispError::new("Nothing before the dot!".to_strbuf(),
+                                              Some(token.pos)))
+                }
+                tail = try!(read_required(tokens, token.pos));
+                match tokens.next() {
+                    Some(TokenAndPos { tok: RParen, .. })   => break,
+                    _   => return Err(RispError::new(
+                            "Expected exactly one datum after the dot!".to_strbuf(),
+                            Some(token.pos)))
+                }
             },
-            Some(tokenize::Operator(op))=> match op {
-                Lambda  => {
-                    terms.push(Operator(Lambda));
-                    let lambda = try!(build_lambda(tokens));
-                    terms.push(Literal(Procedure(box lambda)));
-                },
-                _       => terms.push(Operator(op)),
-            },
-            Some(tokenize::Literal(ty)) => terms.push(Literal(ty)),
-            Some(tokenize::Symbol(x))   => terms.push(Variable(x)),
-            None    => return Ok(terms),
-            _   => {
-                println!("{}", token);
-                return Err(TRANSFAIL.to_strbuf())
-            }
+            _       => terms.push(try!(read_datum(tokens, token)))
         }
     }
+
+    match vec_to_improper_list(terms, tail) {
+        Cons(anterior, dorsal, _)   => Ok(Cons(anterior, dorsal, Some(pos))),
+        list                        => Ok(list)
+    }
 }
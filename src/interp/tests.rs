@@ -0,0 +1,432 @@
+//! Whole programs run through the interpreter, checked by what they print.
+
+use super::{interp, Environment};
+
+///Evaluates `source` in a new global environment.
+fn run(source: &str) -> StrBuf {
+    interp(source.to_strbuf(), &Environment::new_global())
+}
+
+fn assert_prints(source: &str, expected: &str) {
+    assert_eq!(run(source).as_slice(), expected);
+}
+
+fn assert_fails_with(source: &str, msg: &str) {
+    let printed = run(source);
+    assert!(printed.as_slice().contains(msg), "{} printed {}", source, printed);
+}
+
+#[test]
+fn named_let_loops_in_constant_space() {
+    assert_prints("(let loop ((i 0)) (if (= i 1000000) i (loop (+ i 1))))", "1000000");
+}
+
+#[test]
+fn self_tail_calls_run_in_constant_space() {
+    assert_prints("(define count (lambda (i n) (if (= i n) i (count (+ i 1) n))))
+                   (count 0 1000000)", "1000000");
+}
+
+#[test]
+fn tail_calls_through_cond() {
+    assert_prints("(define count (lambda (i) (cond ((= i 1000000) i) (else (count (+ i 1))))))
+                   (count 0)", "1000000");
+}
+
+#[test]
+fn tail_calls_through_and() {
+    assert_prints("(define f (lambda (n) (if (= n 0) #t (and #t (f (- n 1))))))
+                   (f 1000000)", "#t");
+}
+
+#[test]
+fn tail_calls_through_or() {
+    assert_prints("(define f (lambda (n) (if (= n 0) #t (or #f (f (- n 1))))))
+                   (f 1000000)", "#t");
+}
+
+#[test]
+fn errors_in_deep_recursion_unwind() {
+    assert_fails_with("(define f (lambda (n) (if (= n 0) (car 0) (+ 1 (f (- n 1))))))
+                       (f 1000000)", "car: expected a pair");
+}
+
+#[test]
+fn generators_yield_through_shift() {
+    assert_prints("(define k #f)
+                   (define yield (lambda (x) (shift c (set! k c) x)))
+                   (define a (reset (for-each yield (list 1 2 3)) 'done))
+                   (define b (k #f))
+                   (define c (k #f))
+                   (define d (k #f))
+                   (list a b c d)", "(1 2 3 done)");
+}
+
+#[test]
+fn shift_skips_the_rest_of_its_reset() {
+    assert_prints("(define trail '())
+                   (define result
+                     (reset (set! trail (cons 1 trail))
+                            (shift k 'escaped)
+                            (set! trail (cons 2 trail))
+                            'finished))
+                   (list result trail)", "(escaped (1))");
+}
+
+#[test]
+fn exceptions_escape_through_shift() {
+    assert_prints("(define throw (lambda (e) (shift k e)))
+                   (reset (+ 1 (throw 'oops)))", "oops");
+}
+
+#[test]
+fn negative_zero_is_read_as_negative() {
+    assert_prints("(/ 1 -0.0)", "-inf.0");
+    assert_prints("(- 0.0)", "-0.0");
+    assert_prints("(- -0.0)", "0.0");
+    assert_prints("(- 5)", "-5");
+}
+
+#[test]
+fn complex_results_with_no_imaginary_part_are_real() {
+    assert_prints("(* +i +i)", "-1.0");
+    assert_prints("(< (* +i +i) 0)", "#t");
+    assert_prints("(- 1+2i +2i)", "1.0");
+    assert_prints("(/ 2+2i 1+i)", "2.0");
+    assert_prints("(+ 1+2i 1-2i)", "2.0");
+    assert_prints("(* 1+2i 2)", "2.0+4.0i");
+    assert_prints("1-2i", "1.0-2.0i");
+}
+
+#[test]
+fn exact_decimals_are_read_without_rounding() {
+    assert_prints("#e0.1", "1/10");
+    assert_prints("#e1.2", "6/5");
+    assert_prints("#e-1.25", "-5/4");
+    let mut ten_to_the_400 = "1".to_strbuf();
+    for _ in range(0, 400) {
+        ten_to_the_400.push_char('0');
+    }
+    assert_prints("#e1e400", ten_to_the_400.as_slice());
+    assert_prints("#e1.5e-3", "3/2000");
+    assert_prints("#e2.", "2");
+    assert_prints("#i1/2", "0.5");
+}
+
+#[test]
+fn integer_division_signs() {
+    assert_prints("(quotient -7 2)", "-3");
+    assert_prints("(quotient 7 -2)", "-3");
+    assert_prints("(remainder -7 2)", "-1");
+    assert_prints("(remainder 7 -2)", "1");
+    assert_prints("(modulo -7 2)", "1");
+    assert_prints("(modulo 7 -2)", "-1");
+    assert_prints("(modulo -7 -2)", "-1");
+    assert_prints("(floor/ -7 2)", "(-4 1)");
+    assert_prints("(floor/ 7 -2)", "(-4 -1)");
+    assert_prints("(truncate/ -7 2)", "(-3 -1)");
+    assert_prints("(truncate/ 7 -2)", "(-3 1)");
+    assert_prints("(modulo -7.0 2)", "1.0");
+}
+
+#[test]
+fn division_by_zero() {
+    assert_fails_with("(/ 1 0)", "Division by zero!");
+    assert_fails_with("(/ 1.0 0)", "Division by zero!");
+    assert_fails_with("(quotient 1 0)", "Division by zero!");
+    assert_fails_with("(modulo 1.0 0.0)", "Division by zero!");
+    assert_fails_with("(floor/ 1 0)", "Division by zero!");
+    assert_prints("(/ 1 0.0)", "+inf.0");
+    assert_prints("(/ -1.0 0.0)", "-inf.0");
+}
+
+#[test]
+fn round_breaks_ties_to_even() {
+    assert_prints("(round 2.5)", "2.0");
+    assert_prints("(round 3.5)", "4.0");
+    assert_prints("(round -2.5)", "-2.0");
+    assert_prints("(round -3.5)", "-4.0");
+    assert_prints("(round 2.6)", "3.0");
+    assert_prints("(round 5/2)", "2");
+    assert_prints("(round 7/2)", "4");
+    assert_prints("(round -5/2)", "-2");
+}
+
+#[test]
+fn inexactness_is_contagious() {
+    assert_prints("(max 1 2.0)", "2.0");
+    assert_prints("(max 3 2.0)", "3.0");
+    assert_prints("(min 1 2.0)", "1.0");
+    assert_prints("(max 1/2 1/3)", "1/2");
+    assert_prints("(gcd 4 6.0)", "2.0");
+    assert_prints("(gcd 12 18)", "6");
+    assert_prints("(lcm 4.0 6)", "12.0");
+    assert_prints("(lcm 4 6)", "12");
+}
+
+#[test]
+fn floats_print_with_the_fewest_digits() {
+    assert_prints("0.1", "0.1");
+    assert_prints("(+ 0.1 0.2)", "0.30000000000000004");
+    assert_prints("1.5", "1.5");
+    assert_prints("-0.0", "-0.0");
+    assert_prints("1e300", "1e300");
+    assert_prints("1.5e-7", "1.5e-7");
+    assert_prints("123.25", "123.25");
+    assert_prints("10.0", "10.0");
+    assert_prints("100.0", "100.0");
+    assert_prints("(inexact 10)", "10.0");
+    assert_prints("(* 1.0 20)", "20.0");
+    assert_prints("0.001", "0.001");
+    assert_prints("1e21", "1e21");
+    assert_prints("1e20", "100000000000000000000.0");
+    assert_prints("0.000001", "0.000001");
+    assert_prints("1e-7", "1e-7");
+}
+
+#[test]
+fn long_lists_are_freed() {
+    assert_prints("(define xs (iota 1000000)) (define xs 0) xs", "0");
+}
+
+#[test]
+fn long_and_circular_lists_are_compared_in_a_loop() {
+    assert_prints("(equal? (iota 1000000) (iota 1000000))", "#t");
+    assert_prints("(equal? (iota 1000000) (iota 1000001))", "#f");
+    assert_prints("(define xs (list 1 2)) (set-cdr! (cdr xs) xs)
+                   (define ys (list 1 2 1 2)) (set-cdr! (cdr (cdr (cdr ys))) ys)
+                   (equal? xs ys)", "#t");
+    assert_prints("(define xs (list 1 2)) (set-cdr! (cdr xs) xs)
+                   (define ys (list 1 3)) (set-cdr! (cdr ys) ys)
+                   (equal? xs ys)", "#f");
+}
+
+#[test]
+fn print_is_a_procedure() {
+    assert_prints("(print 1)", "()");
+    assert_prints("(map print (list 1 2))", "(() ())");
+    assert_fails_with("(print)", "print takes exactly one value!");
+}
+
+#[test]
+fn built_in_procedures_can_be_rebound() {
+    assert_prints("((lambda (list) (car list)) '(1 2))", "1");
+    assert_prints("(let ((max 0)) max)", "0");
+    assert_prints("(define square (lambda (x) (* x x x))) (square 2)", "8");
+    assert_prints("(define first car) (set! car cdr) (list (first '(1 2)) (car '(1 2)))",
+                  "(1 (2))");
+    assert_prints("(map + '(1 2) '(10 20))", "(11 22)");
+}
+
+#[test]
+fn special_forms_are_not_variables() {
+    assert_fails_with("(map if '(1 2))", "if is a special form, not a procedure!");
+    assert_fails_with("(eval '(car '(1)) (null-environment 5))", "Unbound variable: car");
+}
+
+#[test]
+fn sandboxes_are_their_own_interaction_environment() {
+    assert_prints("(define x 0)
+                   (eval '(eval '(define x 1) (interaction-environment))
+                         (scheme-report-environment 5))
+                   x", "0");
+    assert_prints("(define sandbox (scheme-report-environment 5))
+                   (eval '(eval '(define x 1) (interaction-environment)) sandbox)
+                   (eval 'x sandbox)", "1");
+    assert_prints("(define x 2) (eval 'x (interaction-environment))", "2");
+}
+
+#[test]
+fn collecting_one_global_environment_leaves_others_alone() {
+    let first = Environment::new_global();
+    let second = Environment::new_global();
+    interp("(define make (lambda () (define f (lambda () 1)) f)) (define g (make))".to_strbuf(),
+           &first);
+    interp("(define h (lambda () 2))".to_strbuf(), &second);
+    assert_eq!(interp("(g)".to_strbuf(), &first).as_slice(), "1");
+    assert_eq!(interp("(list (h) (length (list 1 2)))".to_strbuf(), &second).as_slice(), "(2 2)");
+}
+
+#[test]
+fn sandboxes_held_by_the_program_survive_collection() {
+    assert_prints("(define sandbox (scheme-report-environment 5))
+                   (eval '(define f (lambda () (define g (lambda () 3)) (g))) sandbox)
+                   (define x 0)
+                   (eval '(f) sandbox)", "3");
+}
+
+#[test]
+fn errors_from_earlier_inputs_are_placed_in_this_one() {
+    let global = Environment::new_global();
+    interp("\n\n    (define f (lambda () (car 0)))".to_strbuf(), &global);
+    let printed = interp("(f)".to_strbuf(), &global);
+    assert!(printed.as_slice().starts_with("line 1, column 1: car: expected a pair"),
+            "printed {}", printed);
+}
+
+#[test]
+fn errors_after_sub_forms_are_placed_at_the_whole_form() {
+    let printed = run("(define x 1)\n(set! unbound (+ 1 2))");
+    assert!(printed.as_slice().starts_with("line 2, column 1: Cannot set! an unbound variable"),
+            "printed {}", printed);
+    let printed = run("(define x 1)\n  `(1 ,@(car '(2)))");
+    assert!(printed.as_slice().starts_with("line 2, column 3: unquote-splicing needs a list"),
+            "printed {}", printed);
+}
+
+#[test]
+fn comments_run_to_the_end_of_the_line() {
+    assert_prints("(define x 1) ; (define x 2)\nx", "1");
+    assert_prints("(define x 3) x;comment", "3");
+    assert_prints("(list 1;one\n 2)", "(1 2)");
+}
+
+#[test]
+fn escaping_a_dynamic_wind_runs_its_after_thunk() {
+    assert_prints("(define trail '())
+                   (define note (lambda (x) (set! trail (cons x trail))))
+                   (define result
+                     (call/cc (lambda (k)
+                       (dynamic-wind (lambda () (note 'before))
+                                     (lambda () (k 'escaped) (note 'unreached))
+                                     (lambda () (note 'after))))))
+                   (list result (reverse trail))", "(escaped (before after))");
+}
+
+#[test]
+fn reentering_a_dynamic_wind_runs_its_before_thunk() {
+    assert_prints("(define trail '())
+                   (define note (lambda (x) (set! trail (cons x trail))))
+                   (define k #f)
+                   (dynamic-wind (lambda () (note 'before))
+                                 (lambda () (call/cc (lambda (c) (set! k c))) (note 'during))
+                                 (lambda () (note 'after)))
+                   (define again (lambda () (if k (let ((c k)) (set! k #f) (c #f)))))
+                   (again)
+                   (reverse trail)", "(before during after before during after)");
+}
+
+#[test]
+fn continuations_can_be_called_from_later_inputs() {
+    assert_prints("(define k #f)
+                   (+ 1 (call/cc (lambda (c) (set! k c) 1)))
+                   (k 10)", "11");
+}
+
+#[test]
+fn macro_bindings_do_not_capture_the_callers_variables() {
+    assert_prints("(define-syntax swap!
+                     (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))
+                   (define tmp 1)
+                   (define y 2)
+                   (swap! tmp y)
+                   (list tmp y)", "(2 1)");
+    assert_prints("(define-syntax my-or
+                     (syntax-rules () ((_ a b) (let ((t a)) (if t t b)))))
+                   (let ((t 5)) (my-or #f t))", "5");
+}
+
+#[test]
+fn free_identifiers_in_macros_mean_what_they_did_where_it_was_defined() {
+    assert_prints("(define-syntax pair-up
+                     (syntax-rules () ((_ a b) (if #t (list a b) #f))))
+                   (let ((list 5)) (pair-up 1 list))", "(1 5)");
+}
+
+#[test]
+fn macros_expand_one_step_or_all_the_way() {
+    assert_prints("(defmacro inc! (x) `(set! ,x (+ ,x 1)))
+                   (define-macro (inc-once! x) `(inc! ,x))
+                   (list (macroexpand-1 '(inc-once! y)) (macroexpand '(inc-once! y)))",
+                  "((inc! y) (set! y (+ y 1)))");
+    assert_prints("(defmacro inc! (x) `(set! ,x (+ ,x 1)))
+                   (define-macro inc-twice! (lambda (x) `(begin (inc! ,x) (inc! ,x))))
+                   (define y 0)
+                   (inc-twice! y)
+                   y", "2");
+}
+
+#[test]
+fn gensyms_cannot_be_captured() {
+    assert_prints("(defmacro my-or (a b) (let ((t (gensym))) `(let ((,t ,a)) (if ,t ,t ,b))))
+                   (let ((t 5)) (my-or #f t))", "5");
+    assert_prints("(eq? (gensym) (gensym))", "#f");
+}
+
+#[test]
+fn unquote_splicing_works_anywhere_in_a_list() {
+    assert_prints("`(1 ,@(list 2 3) 4)", "(1 2 3 4)");
+    assert_prints("`(1 ,@(list 2 3))", "(1 2 3)");
+    assert_prints("`(,@'() 1)", "(1)");
+}
+
+#[test]
+fn nested_quasiquotes_only_evaluate_the_outermost_level() {
+    assert_prints("`(1 `(2 ,(3 ,(+ 1 3))))",
+                  "(1 (quasiquote (2 (unquote (3 4)))))");
+}
+
+#[test]
+fn integers_overflow_into_bignums_and_back() {
+    assert_prints("(* 9223372036854775807 2)", "18446744073709551614");
+    assert_prints("(- (* 9223372036854775807 2) 9223372036854775807)", "9223372036854775807");
+    assert_prints("(- -9223372036854775808)", "9223372036854775808");
+    assert_prints("(+ 9223372036854775807 1)", "9223372036854775808");
+}
+
+#[test]
+fn roots_and_powers() {
+    assert_prints("(sqrt 16)", "4");
+    assert_prints("(sqrt 1/4)", "1/2");
+    assert_prints("(exact? (sqrt 2))", "#f");
+    assert_prints("(exact-integer-sqrt 17)", "(4 1)");
+    assert_prints("(expt 0.0 -1)", "+inf.0");
+    assert_prints("(expt 0 -1.0)", "+inf.0");
+    assert_prints("(expt 0.0 2)", "0.0");
+    assert_prints("(expt 2 10)", "1024");
+}
+
+#[test]
+fn bitwise_operations_on_negative_bignums() {
+    assert_prints("(bitwise-and -1 (expt 2 70))", "1180591620717411303424");
+    assert_prints("(bitwise-xor -5 (expt 2 64))", "-18446744073709551621");
+    assert_prints("(bitwise-or -12 (expt 2 65))", "-12");
+    assert_prints("(bitwise-not (expt 2 70))", "-1180591620717411303425");
+    assert_prints("(arithmetic-shift -1 -100)", "-1");
+    assert_prints("(arithmetic-shift 1 70)", "1180591620717411303424");
+}
+
+#[test]
+fn cond_and_case_clauses_can_pass_on_their_values() {
+    assert_prints("(cond (#f 'no) ((+ 1 1) => (lambda (x) (* x 10))) (else 'none))", "20");
+    assert_prints("(case (* 2 3) ((2 3 5 7) 'prime) ((1 4 6 8 9) 'composite))", "composite");
+    assert_prints("(case 5 ((1 2) 'low) (else => (lambda (x) (* x 2))))", "10");
+}
+
+#[test]
+fn when_and_unless_run_their_bodies_on_a_condition() {
+    assert_prints("(define x 0) (when (> 1 0) (set! x 1) (+ x 1))", "2");
+    assert_prints("(when #f 'no)", "()");
+    assert_prints("(unless (> 1 0) 'no)", "()");
+    assert_prints("(define x 0) (unless #f (set! x 1) (+ x 2))", "3");
+}
+
+#[test]
+fn set_only_changes_existing_bindings() {
+    assert_fails_with("(set! nowhere 1)", "Cannot set! an unbound variable: nowhere");
+    assert_prints("(define x 1) ((lambda () (set! x 2))) x", "2");
+}
+
+#[test]
+fn internal_definitions_are_letrec_star() {
+    assert_prints("(define f (lambda ()
+                     (define even? (lambda (n) (if (= n 0) #t (odd? (- n 1)))))
+                     (define odd? (lambda (n) (if (= n 0) #f (even? (- n 1)))))
+                     (even? 10)))
+                   (f)", "#t");
+    assert_fails_with("(define f (lambda () (define a b) (define b 1) a)) (f)",
+                      "b is used before its definition");
+    assert_fails_with("(define b 5) (define f (lambda () (define a b) (define b 1) a)) (f)",
+                      "b is used before its definition");
+    assert_prints("(define f (lambda () (define a 1) (define c (+ a 1)) c)) (f)", "2");
+}
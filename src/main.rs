@@ -5,14 +5,12 @@
 
 extern crate libc;
 extern crate getopts;
-extern crate collections;
 
 use std::os;
 use libc::c_char;
 use std::c_str::CString;
 use interp::{interp, Environment};
 use getopts::{optopt, getopts};
-use collections::HashMap;
 
 pub mod interp;
 
@@ -58,10 +56,7 @@ pub fn rust_add_history(line: &str) {
 }
 
 fn main() {
-    let mut global_env = Environment {
-        variables: HashMap::new(),
-        parent: None
-    };
+    let global_env = Environment::new_global();
 
     let args: Vec<StrBuf> = os::args().iter().map(|x| x.to_strbuf()).collect();
 
@@ -77,7 +72,7 @@ fn main() {
     if matches.opt_present("n") {
         match matches.opt_str("n") {
             Some(sexpr) => {
-                let msg = interp(sexpr.to_str(), &mut global_env);
+                let msg = interp(sexpr.to_str(), &global_env);
                 println!("{}", msg);
                 return
             }
@@ -154,7 +149,7 @@ fn main() {
             _   => { }
         }
 
-        let msg = interp(expr.as_slice().trim().to_str(), &mut global_env);
+        let msg = interp(expr.as_slice().trim().to_str(), &global_env);
         //holy hell I hate this
         println!("{}", msg);
     }